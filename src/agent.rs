@@ -0,0 +1,333 @@
+use crate::cli::Approval;
+use crate::groq::{GroqClient, Message};
+use crate::handler::{self, ReplyOutcome};
+use crate::tts::Speaker;
+use colored::*;
+use std::{
+    env, fmt,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+};
+
+const DEFAULT_MAX_STEPS: usize = 8;
+const DEFAULT_MAX_REPEATS: usize = 2;
+const DEFAULT_MAX_FAILURES: usize = 3;
+
+/// Why the agent loop was stopped before the model signalled `DONE:`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    StepLimit(usize),
+    RepeatedCommand(String),
+    RepeatedFailures(usize),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::StepLimit(n) => write!(f, "step limit of {} commands reached", n),
+            StopReason::RepeatedCommand(cmd) => write!(f, "command repeated: {}", cmd),
+            StopReason::RepeatedFailures(n) => write!(f, "{} commands failed in a row", n),
+        }
+    }
+}
+
+struct StepRecord {
+    cmd: String,
+    exit_code: i32,
+}
+
+/// Tracks the commands run for a single user request and decides when to stop
+pub struct StepBudget {
+    max_steps: usize,
+    max_repeats: usize,
+    max_failures: usize,
+    steps: Vec<StepRecord>,
+    consecutive_failures: usize,
+}
+
+impl StepBudget {
+    pub fn new(max_steps: usize, max_repeats: usize, max_failures: usize) -> Self {
+        Self {
+            max_steps,
+            max_repeats,
+            max_failures,
+            steps: Vec::new(),
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Reads `AI_MAX_STEPS`, `AI_MAX_REPEATS` and `AI_MAX_FAILURES`, falling back to defaults
+    pub fn from_env() -> Self {
+        Self::new(
+            env_usize("AI_MAX_STEPS", DEFAULT_MAX_STEPS),
+            env_usize("AI_MAX_REPEATS", DEFAULT_MAX_REPEATS),
+            env_usize("AI_MAX_FAILURES", DEFAULT_MAX_FAILURES),
+        )
+    }
+
+    /// Clears the recorded steps so the budget can be reused for the next request
+    pub fn reset(&mut self) {
+        self.steps.clear();
+        self.consecutive_failures = 0;
+    }
+
+    /// Called before running a command: refuses commands already run too often
+    pub fn check_command(&self, cmd: &str) -> Option<StopReason> {
+        if self.steps.len() >= self.max_steps {
+            return Some(StopReason::StepLimit(self.max_steps));
+        }
+
+        let repeats = self.steps.iter().filter(|s| s.cmd == cmd).count();
+        if repeats >= self.max_repeats {
+            return Some(StopReason::RepeatedCommand(cmd.to_string()));
+        }
+
+        None
+    }
+
+    /// Called after running a command: records it and reports if the loop must stop
+    pub fn record(&mut self, cmd: &str, exit_code: i32) -> Option<StopReason> {
+        self.steps.push(StepRecord {
+            cmd: cmd.to_string(),
+            exit_code,
+        });
+
+        if exit_code == 0 {
+            self.consecutive_failures = 0;
+        } else {
+            self.consecutive_failures += 1;
+        }
+
+        if self.consecutive_failures >= self.max_failures {
+            return Some(StopReason::RepeatedFailures(self.consecutive_failures));
+        }
+
+        if self.steps.len() >= self.max_steps {
+            return Some(StopReason::StepLimit(self.max_steps));
+        }
+
+        None
+    }
+
+    /// Whether the most recent command in this request failed
+    pub fn last_failed(&self) -> bool {
+        self.steps.last().is_some_and(|s| s.exit_code != 0)
+    }
+
+    /// Human-readable recap of what ran before the loop was stopped
+    pub fn summary(&self, reason: &StopReason) -> String {
+        let mut out = format!("Stopped: {}\n", reason);
+        for (i, step) in self.steps.iter().enumerate() {
+            let status = if step.exit_code == 0 { "ok" } else { "failed" };
+            out.push_str(&format!(
+                "  {}. [{} / exit {}] {}\n",
+                i + 1,
+                status,
+                step.exit_code,
+                step.cmd
+            ));
+        }
+        out
+    }
+}
+
+fn env_usize(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(default)
+}
+
+/// The system prompt describing the reply protocol
+pub fn system_message(system_info: &str) -> Message {
+    Message {
+        role: "system".into(),
+        content: format!(
+            r#"
+            You are an AI-powered terminal assistant.
+            
+            SYSTEM INFORMATION:
+            {}
+
+            PROTOCOL:
+            MSG: <text>
+            CMD: <linux command>
+            DONE: <short summary of what was achieved>
+
+            For tasks that need several commands, propose a plan instead of CMD:
+            PLAN: <goal>
+            STEP: <linux command>
+            WHY: <reason for this step>
+            (repeat STEP/WHY for every step, in order)
+            
+            RULES:
+            - After CMD execution, you will receive COMMAND_OUTPUT.
+            - You MUST verify the output before claiming success.
+            - COMMAND_OUTPUT lists files_created, files_modified and files_deleted in the working directory; check them against what you intended.
+            - When the task is complete, reply with DONE: and no CMD.
+            - Never repeat a command that already failed; try something different.
+            - After a PLAN runs you receive PLAN_RESULT; if a step failed, send a revised PLAN.
+            - COMMAND_EDITED means the user corrected your command; learn from the edit.
+            - COMMAND_REJECTED or PLAN_REJECTED carries the user's reason; propose an alternative that respects it.
+            - PIPED_INPUT is content the user piped in (logs, command output); use it as context.
+            - ATTACHED_FILE holds a file the user mentioned as @path, with line numbers; ATTACHED_IMAGE names a mentioned image that is included in the message.
+            - Images (screenshots, attachments) are part of the conversation; look at them rather than guessing their content.
+            "#,
+            system_info
+        ).into(),
+    }
+}
+
+/// How a single user request ended
+pub enum RequestOutcome {
+    Finished,
+    Cancelled,
+    Stopped,
+    Failed,
+}
+
+/// Keep asking the model and handling its replies until the request is finished.
+/// The user's prompt must already be the last message in `history`.
+pub async fn run_request(
+    history: &mut Vec<Message>,
+    current_dir: &mut PathBuf,
+    has_display: bool,
+    groq_client: &GroqClient,
+    budget: &mut StepBudget,
+    approval: Approval,
+    speaker: Option<&Speaker>,
+) -> RequestOutcome {
+    budget.reset();
+
+    loop {
+        // 1. Call AI (with a spinner when someone is watching)
+        let reply_result = if io::stdout().is_terminal() {
+            with_spinner(groq_client.chat(history.clone())).await
+        } else {
+            groq_client.chat(history.clone()).await
+        };
+
+        // 2. Handle the result
+        let reply = match reply_result {
+            Ok(r) => r,
+            Err(err) => {
+                println!("{} {}", "Error:".red(), err);
+                return RequestOutcome::Failed;
+            }
+        };
+
+        // Speak the MSG: part while the rest is handled
+        if let Some(speaker) = speaker {
+            speaker.say(&handler::parse_reply(&reply).msg);
+        }
+
+        let outcome =
+            handler::handle_reply(&reply, history, current_dir, has_display, budget, approval)
+                .await;
+
+        match outcome {
+            ReplyOutcome::Continue => {}
+            ReplyOutcome::Finished => return RequestOutcome::Finished,
+            ReplyOutcome::Cancelled => return RequestOutcome::Cancelled,
+            ReplyOutcome::Stopped(reason) => {
+                println!("{}", budget.summary(&reason).yellow());
+                history.push(Message {
+                    role: "user".into(),
+                    content: format!("STOPPED: {}", reason).into(),
+                });
+                return RequestOutcome::Stopped;
+            }
+        }
+    }
+}
+
+async fn with_spinner<T>(fut: impl Future<Output = T>) -> T {
+    // 1. Create spinner channel
+    let (tx, mut rx) = tokio::sync::oneshot::channel();
+
+    // 2. Spawn the spinner in a separate background task
+    let spinner_handle = tokio::spawn(async move {
+        let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+        let mut i = 0;
+        loop {
+            // Check if we received the stop signal
+            if rx.try_recv().is_ok() {
+                break;
+            }
+
+            print!("\r{} AI thinking...", frames[i % frames.len()].cyan());
+            io::stdout().flush().unwrap();
+
+            tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+            i += 1;
+        }
+        // Clear the spinner line when done
+        print!("\r\x1b[K");
+        io::stdout().flush().unwrap();
+    });
+
+    let result = fut.await;
+
+    // 3. Stop spinner
+    let _ = tx.send(());
+    spinner_handle.await.unwrap(); // Wait for spinner to clean up
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_step_limit() {
+        let mut budget = StepBudget::new(3, 10, 10);
+        assert_eq!(budget.record("a", 0), None);
+        assert_eq!(budget.record("b", 0), None);
+        assert_eq!(budget.check_command("c"), None);
+        assert_eq!(budget.record("c", 0), Some(StopReason::StepLimit(3)));
+        assert_eq!(budget.check_command("d"), Some(StopReason::StepLimit(3)));
+
+        budget.reset();
+        assert_eq!(budget.check_command("d"), None);
+    }
+
+    #[test]
+    fn refuses_identical_commands_past_the_repeat_limit() {
+        let mut budget = StepBudget::new(10, 2, 10);
+        budget.record("make", 1);
+        assert_eq!(budget.check_command("make"), None);
+        budget.record("make", 1);
+        assert_eq!(
+            budget.check_command("make"),
+            Some(StopReason::RepeatedCommand("make".into()))
+        );
+        // Only exact repeats count
+        assert_eq!(budget.check_command("make test"), None);
+    }
+
+    #[test]
+    fn success_resets_the_failure_streak() {
+        let mut budget = StepBudget::new(10, 10, 3);
+        assert_eq!(budget.record("a", 1), None);
+        assert_eq!(budget.record("b", 2), None);
+        assert_eq!(budget.record("c", 0), None);
+        assert_eq!(budget.record("d", 1), None);
+        assert_eq!(budget.record("e", 1), None);
+        assert_eq!(
+            budget.record("f", 127),
+            Some(StopReason::RepeatedFailures(3))
+        );
+    }
+
+    #[test]
+    fn last_failed_follows_the_latest_command() {
+        let mut budget = StepBudget::new(10, 10, 10);
+        assert!(!budget.last_failed());
+        budget.record("false", 1);
+        assert!(budget.last_failed());
+        budget.record("true", 0);
+        assert!(!budget.last_failed());
+    }
+}
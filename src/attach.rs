@@ -0,0 +1,272 @@
+use crate::groq::{self, Content, ContentPart, Message};
+use colored::*;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Larger text files are cut off; the model rarely needs all of a big file
+const MAX_FILE_BYTES: usize = 64 * 1024;
+/// Larger images are refused before they are read; smaller ones get downscaled
+const MAX_IMAGE_FILE_BYTES: u64 = 32 * 1024 * 1024;
+const MAX_LINES: usize = 400;
+const MAX_ATTACHMENTS: usize = 5;
+
+/// `@path` words in a prompt that name existing files, resolved against `cwd`.
+/// Trailing punctuation is dropped so `look at @main.rs.` still works, and
+/// `user@host` is left alone because the `@` must start a word.
+pub fn mentions(prompt: &str, cwd: &Path) -> Vec<(String, PathBuf)> {
+    let mut found: Vec<(String, PathBuf)> = Vec::new();
+
+    for word in prompt.split_whitespace() {
+        let Some(raw) = word.strip_prefix('@') else {
+            continue;
+        };
+        let candidates = [
+            raw,
+            raw.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"']),
+        ];
+        let Some((name, path)) = candidates
+            .iter()
+            .filter(|c| !c.is_empty())
+            .map(|c| (c.to_string(), resolve(c, cwd)))
+            .find(|(_, p)| p.is_file())
+        else {
+            continue;
+        };
+        if !found.iter().any(|(_, p)| *p == path) {
+            found.push((name, path));
+        }
+    }
+
+    found
+}
+
+/// Build the user message for `prompt` with every `@`-mentioned file attached:
+/// text files appended with line numbers, images as image parts the model can see.
+/// Prints what was attached.
+pub fn message(prompt: &str, cwd: &Path) -> Message {
+    let mentioned = mentions(prompt, cwd);
+
+    let mut text = prompt.to_string();
+    let mut images = Vec::new();
+    for (name, path) in mentioned.iter().take(MAX_ATTACHMENTS) {
+        let (bytes, complete) = match read_attachment(path) {
+            Ok(Attachment::Text { bytes, complete }) => (bytes, complete),
+            Ok(Attachment::Image(bytes)) => {
+                match ContentPart::image(&bytes) {
+                    Ok(image) => {
+                        println!(
+                            "{} {} {}",
+                            "Attached".green(),
+                            name.cyan(),
+                            "(image)".dimmed()
+                        );
+                        text.push_str(&format!("\n\nATTACHED_IMAGE: {}", name));
+                        images.push(image);
+                    }
+                    Err(e) => println!("{} {}: {}", "Not attached:".yellow(), name, e),
+                }
+                continue;
+            }
+            Err(e) => {
+                println!("{} {}: {}", "Not attached:".yellow(), name, e);
+                continue;
+            }
+        };
+
+        match number_lines(&bytes) {
+            Some((numbered, shown, total)) => {
+                let range = if !complete {
+                    format!("first {} lines", shown)
+                } else if shown < total {
+                    format!("lines 1-{} of {}", shown, total)
+                } else if total == 1 {
+                    "1 line".to_string()
+                } else {
+                    format!("{} lines", total)
+                };
+                println!(
+                    "{} {} {}",
+                    "Attached".green(),
+                    name.cyan(),
+                    format!("({})", range).dimmed()
+                );
+                text.push_str(&format!(
+                    "\n\nATTACHED_FILE: {} ({})\n```\n{}```",
+                    name, range, numbered
+                ));
+            }
+            None => println!("{} {} (binary file)", "Not attached:".yellow(), name),
+        }
+    }
+
+    if mentioned.len() > MAX_ATTACHMENTS {
+        println!(
+            "{} only the first {} files are attached",
+            "Note:".yellow(),
+            MAX_ATTACHMENTS
+        );
+    }
+
+    let content = if images.is_empty() {
+        Content::Text(text)
+    } else {
+        let mut parts = vec![ContentPart::Text { text }];
+        parts.extend(images);
+        Content::Parts(parts)
+    };
+    Message {
+        role: "user".into(),
+        content,
+    }
+}
+
+/// Read an image file into an image part, downscaled if needed
+pub fn image_part(path: &Path) -> Result<ContentPart, String> {
+    match read_attachment(path)? {
+        Attachment::Image(bytes) => ContentPart::image(&bytes).map_err(|e| e.to_string()),
+        Attachment::Text { .. } => {
+            Err("Unsupported image format (expected PNG, JPEG, WebP or GIF)".into())
+        }
+    }
+}
+
+/// A file's contents, read no further than the attachment limits
+enum Attachment {
+    Image(Vec<u8>),
+    /// The first `MAX_FILE_BYTES`; `complete` if that was the whole file
+    Text {
+        bytes: Vec<u8>,
+        complete: bool,
+    },
+}
+
+/// Read the start of `path`, and the rest only for images within
+/// `MAX_IMAGE_FILE_BYTES`. Bounded even for files that report no size
+/// or keep growing, like `/proc` entries and logs.
+fn read_attachment(path: &Path) -> Result<Attachment, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+
+    let mut bytes = Vec::new();
+    (&mut file)
+        .take(MAX_FILE_BYTES as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if groq::sniff_mime(&bytes).is_none() {
+        let complete = bytes.len() <= MAX_FILE_BYTES;
+        bytes.truncate(MAX_FILE_BYTES);
+        return Ok(Attachment::Text { bytes, complete });
+    }
+
+    let too_large = || {
+        format!(
+            "image larger than {} MiB",
+            MAX_IMAGE_FILE_BYTES / 1024 / 1024
+        )
+    };
+    if len > MAX_IMAGE_FILE_BYTES {
+        return Err(too_large());
+    }
+    file.take(MAX_IMAGE_FILE_BYTES + 1 - bytes.len() as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() as u64 > MAX_IMAGE_FILE_BYTES {
+        return Err(too_large());
+    }
+    Ok(Attachment::Image(bytes))
+}
+
+/// Number the lines of a text file, stopping at `MAX_LINES` or `MAX_FILE_BYTES`.
+/// Returns the text with the number of lines shown and in total, or None for binary data.
+fn number_lines(bytes: &[u8]) -> Option<(String, usize, usize)> {
+    if bytes.iter().take(8192).any(|&b| b == 0) {
+        return None;
+    }
+    // A cut-off file may end partway through a character
+    let content = match std::str::from_utf8(bytes) {
+        Ok(content) => content,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&bytes[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    let total = content.lines().count();
+
+    let mut text = String::new();
+    let mut shown = 0;
+    for (i, line) in content.lines().enumerate() {
+        if shown >= MAX_LINES || text.len() + line.len() > MAX_FILE_BYTES {
+            break;
+        }
+        text.push_str(&format!("{:>4} | {}\n", i + 1, line));
+        shown += 1;
+    }
+    Some((text, shown, total))
+}
+
+/// A path as typed: `~/` is the home directory, anything relative is under `cwd`
+pub fn resolve(name: &str, cwd: &Path) -> PathBuf {
+    match name.strip_prefix("~/") {
+        Some(rest) => dirs_next::home_dir().unwrap_or_default().join(rest),
+        None => cwd.join(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn mentions_resolve_existing_files_once() {
+        let dir =
+            std::env::temp_dir().join(format!("ai-terminal-test-attach-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("notes.txt"), "todo").unwrap();
+
+        let found = mentions(
+            "look at @main.rs. and (@notes.txt) then @main.rs again, ssh me@host, @missing.rs",
+            &dir,
+        );
+        let names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+        // `(@notes.txt)` doesn't start with `@`; `me@host` isn't a mention
+        assert_eq!(names, ["main.rs"]);
+        assert_eq!(found[0].1, dir.join("main.rs"));
+
+        let found = mentions("compare @notes.txt, @main.rs!", &dir);
+        let names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["notes.txt", "main.rs"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn number_lines_stops_at_max_lines() {
+        let text: String = (1..=MAX_LINES + 10)
+            .map(|i| format!("line {}\n", i))
+            .collect();
+        let (numbered, shown, total) = number_lines(text.as_bytes()).unwrap();
+        assert_eq!((shown, total), (MAX_LINES, MAX_LINES + 10));
+        assert!(numbered.starts_with("   1 | line 1\n"));
+        assert!(numbered.ends_with(&format!("{} | line {}\n", MAX_LINES, MAX_LINES)));
+    }
+
+    #[test]
+    fn number_lines_handles_a_cut_off_character() {
+        let mut bytes = "naïve\ncafé".as_bytes().to_vec();
+        bytes.pop(); // half of the é
+        let (numbered, shown, _) = number_lines(&bytes).unwrap();
+        assert_eq!(shown, 2);
+        assert_eq!(numbered, "   1 | naïve\n   2 | caf\n");
+    }
+
+    #[test]
+    fn number_lines_rejects_binary() {
+        assert!(number_lines(b"\x7fELF\x02\x01\x01\x00\x00\x00").is_none());
+        // Invalid UTF-8 in the middle is not a cut-off tail
+        assert!(number_lines(b"abc\xff\xfedef").is_none());
+    }
+}
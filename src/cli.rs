@@ -0,0 +1,108 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// AI-powered terminal assistant. Without a request it starts the interactive REPL.
+#[derive(Parser, Debug)]
+#[command(name = "ai-terminal", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Run a single request and exit, e.g. `ai-terminal "find large files"`
+    pub request: Vec<String>,
+
+    /// Run proposed commands without asking (dangerous commands are still refused)
+    #[arg(short = 'y', long, global = true, conflicts_with = "no_exec")]
+    pub yes: bool,
+
+    /// Only print proposed commands, never run them
+    #[arg(short = 'n', long, global = true)]
+    pub no_exec: bool,
+
+    /// Working directory to start in
+    #[arg(short = 'C', long, global = true, value_name = "DIR")]
+    pub cwd: Option<PathBuf>,
+
+    /// Chat model to use (overrides AI_MODEL)
+    #[arg(short, long, global = true)]
+    pub model: Option<String>,
+
+    /// Config file in KEY=VALUE format (default: <config dir>/ai-terminal/config.env)
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print a keybinding (Alt-A) that turns the current command line into a suggestion
+    ShellInit { shell: Shell },
+
+    /// Diagnose the last failed command recorded by the shell-init hook
+    Why,
+
+    /// Explain what a command does, part by part, without running it
+    Explain {
+        /// The command to explain (quote it)
+        command: Vec<String>,
+    },
+
+    /// Print a proposed command for a shell buffer without running it (used by shell-init)
+    Suggest {
+        /// The current command line, or `# <request>` in natural language
+        line: Vec<String>,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Cli {
+    /// The one-shot request, if any words were given
+    pub fn request(&self) -> Option<String> {
+        let request = self.request.join(" ");
+        let request = request.trim();
+        if request.is_empty() {
+            None
+        } else {
+            Some(request.to_string())
+        }
+    }
+
+    pub fn approval(&self) -> Approval {
+        if self.no_exec {
+            Approval::NoExec
+        } else if self.yes {
+            Approval::Yes
+        } else {
+            Approval::Ask
+        }
+    }
+}
+
+/// How proposed commands get approved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Approval {
+    /// Prompt the user for every command
+    Ask,
+    /// Run without prompting, except commands the policy marks dangerous
+    Yes,
+    /// Show the command but never run it
+    NoExec,
+}
+
+/// Process exit codes for one-shot mode
+pub mod exit {
+    pub const SUCCESS: u8 = 0;
+    /// A command ran and failed, or the step budget stopped the loop
+    pub const COMMAND_FAILED: u8 = 1;
+    /// Bad arguments or missing configuration
+    pub const USAGE: u8 = 2;
+    /// The model could not be reached
+    pub const API_ERROR: u8 = 3;
+    /// The user (or policy) declined the proposed command
+    pub const DECLINED: u8 = 4;
+}
@@ -1,3 +1,6 @@
+use crate::screenshot;
+use crate::workspace::{Changes, Snapshot};
+use crate::{flags, notfound};
 use colored::*;
 use std::{
     fs,
@@ -6,16 +9,18 @@ use std::{
 };
 
 const MAX_CAPTURE_LEN: usize = 800;
+const MAX_SUGGESTIONS: usize = 5;
 
 pub struct CommandResult {
     pub exit_code: i32,
     pub user_view: String,
     pub ai_view: String,
-    pub suggestion: Option<String>,    // 👈 NEW FEATURE OUTPUT
-    pub created_file: Option<PathBuf>, // 👈 ADD THIS
+    pub suggestions: Vec<String>, // 👈 NEW FEATURE OUTPUT (best first)
+    pub changes: Changes,         // files created/modified/deleted under `dir`
 }
 
-pub fn execute_and_capture(cmd: &str, dir: &PathBuf) -> CommandResult {
+pub fn execute_and_capture(cmd: &str, dir: &Path) -> CommandResult {
+    let before = Snapshot::take(dir);
     let output = Command::new("sh")
         .arg("-c")
         .arg(cmd)
@@ -29,41 +34,44 @@ pub fn execute_and_capture(cmd: &str, dir: &PathBuf) -> CommandResult {
             let stderr = truncate(&String::from_utf8_lossy(&out.stderr));
 
             // 🔍 Typo fix (existing feature)
-            let suggestion = if code != 0 {
+            let (suggestions, help) = if code != 0 {
                 suggest_fix(cmd, &stderr, dir)
             } else {
-                None
+                (Vec::new(), None)
             };
 
-            // 🖼️ NEW: detect screenshot creation
-            let created_file = if code == 0 && is_screenshot_command(cmd) {
-                let candidates = ["screenshot.png", "Screenshot.png", "screen.png"];
+            let changes = before
+                .as_ref()
+                .and_then(|before| Some(before.diff(&Snapshot::take(dir)?)))
+                .unwrap_or_default();
 
-                candidates
-                    .iter()
-                    .map(|name| dir.join(name))
-                    .find(|path| path.exists())
-            } else {
-                None
-            };
-
-            let user_view = if code == 0 {
+            let mut user_view = if code == 0 {
                 format!("{}\n{}", "✔ Success".green().bold(), stdout)
             } else {
                 format!("{}\n{}", "✖ Failed".red().bold(), stderr)
             };
 
-            let ai_view = format!(
+            let mut ai_view = format!(
                 "command: {}\nexit_code: {}\nstdout:\n{}\nstderr:\n{}",
                 cmd, code, stdout, stderr
             );
 
+            if !changes.is_empty() {
+                user_view.push_str(&format!("\n{}", changes.user_view()));
+                ai_view.push_str(&format!("\n{}", changes.ai_view()));
+            }
+
+            if let Some(help) = &help {
+                user_view.push_str(&format!("\n{} {}", "Help".dimmed(), help.dimmed()));
+                ai_view.push_str(&format!("\nhelp {}", help));
+            }
+
             CommandResult {
                 exit_code: code,
                 user_view,
                 ai_view,
-                suggestion,
-                created_file, // 👈 ADD THIS
+                suggestions,
+                changes,
             }
         }
 
@@ -71,37 +79,111 @@ pub fn execute_and_capture(cmd: &str, dir: &PathBuf) -> CommandResult {
             exit_code: -1,
             user_view: format!("{} {}", "✖ Error:".red().bold(), e),
             ai_view: e.to_string(),
-            suggestion: None,
-            created_file: None, // 👈 ADD THIS
+            suggestions: Vec::new(),
+            changes: Changes::default(),
         },
     }
 }
 
-/// 🔧 Suggest a fixed command if the failure looks like a filename typo
-fn suggest_fix(cmd: &str, stderr: &str, cwd: &Path) -> Option<String> {
-    let missing = extract_missing_path(stderr)?;
+/// 🔧 Suggest fixed commands if the failure looks like a typo in a program,
+/// flag or file name, best first. Flag fixes come with the help lines they are based on.
+pub fn suggest_fix(cmd: &str, stderr: &str, cwd: &Path) -> (Vec<String>, Option<String>) {
+    if let Some(fix) = flags::suggest(cmd, stderr) {
+        return (fix.commands, Some(fix.excerpt));
+    }
 
-    let entries = fs::read_dir(cwd).ok()?;
+    // `gti: not found` is a program typo, `./scirpt.sh: not found` a path typo
+    let missing = match notfound::extract_missing_command(stderr) {
+        Some(name) if !name.contains('/') => return (notfound::suggest(cmd, &name), None),
+        Some(path) => Some(path),
+        None => extract_missing_path(stderr),
+    };
+    let Some(missing) = missing else {
+        return (Vec::new(), None);
+    };
+    if missing.is_empty() || !cmd.contains(&missing) {
+        return (Vec::new(), None);
+    }
 
-    let mut best_match: Option<String> = None;
-    let mut best_score = usize::MAX;
+    // Replace only the first occurrence of the typo
+    let fixes = correct_path(&missing, cwd)
+        .into_iter()
+        .map(|corrected| cmd.replacen(&missing, &corrected, 1))
+        .collect();
+    (fixes, None)
+}
+
+/// Correct every component of `path` against what exists on disk
+/// (`src/hanlder.rs` -> `src/handler.rs`), ranked by total edit distance
+pub fn correct_path(path: &str, cwd: &Path) -> Vec<String> {
+    let absolute = path.starts_with('/');
+    let start = if absolute {
+        PathBuf::from("/")
+    } else {
+        cwd.to_path_buf()
+    };
 
-    for entry in entries.flatten() {
-        let name = entry.file_name().to_string_lossy().to_string();
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
 
-        let score = simple_distance(&missing, &name);
+    // (path as the user would type it, directory on disk, total distance)
+    let mut frontier: Vec<(String, PathBuf, usize)> =
+        vec![(if absolute { "/" } else { "" }.to_string(), start, 0)];
 
-        // Heuristic threshold (small typos only)
-        if score < best_score && score <= 2 {
-            best_score = score;
-            best_match = Some(name);
+    for (i, component) in components.iter().enumerate() {
+        let is_last = i + 1 == components.len();
+        let mut next = Vec::new();
+
+        for (shown, dir, dist) in &frontier {
+            let join = |name: &str| {
+                if shown.is_empty() || shown.ends_with('/') {
+                    format!("{}{}", shown, name)
+                } else {
+                    format!("{}/{}", shown, name)
+                }
+            };
+
+            let exact = dir.join(component);
+            if *component == "." || *component == ".." || exact.exists() {
+                next.push((join(component), exact, *dist));
+                continue;
+            }
+
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                // Only the last component may be a file
+                if !is_last && !path.is_dir() {
+                    continue;
+                }
+                let name = entry.file_name().to_string_lossy().to_string();
+                let d = edit_distance(component, &name);
+                if d <= max_typos(component) {
+                    next.push((join(&name), path, dist + d));
+                }
+            }
         }
+
+        next.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+        next.truncate(MAX_SUGGESTIONS);
+        frontier = next;
     }
 
-    let corrected = best_match?;
+    frontier
+        .into_iter()
+        .filter(|(_, _, dist)| *dist > 0)
+        .map(|(shown, _, _)| shown)
+        .collect()
+}
 
-    // Replace only the first occurrence of the typo
-    Some(cmd.replacen(&missing, &corrected, 1))
+/// Heuristic threshold: short names only tolerate one typo
+pub fn max_typos(name: &str) -> usize {
+    match name.chars().count() {
+        0..=3 => 1,
+        4..=8 => 2,
+        _ => 3,
+    }
 }
 
 /// Extract missing file name from common Unix errors
@@ -129,43 +211,182 @@ fn extract_missing_path(stderr: &str) -> Option<String> {
     stderr.split(':').nth(1).map(|s| s.trim().to_string())
 }
 
-/// Very small, safe distance function (no external crates)
-fn simple_distance(a: &str, b: &str) -> usize {
-    let a_bytes = a.as_bytes();
-    let b_bytes = b.as_bytes();
+/// Damerau-Levenshtein distance (optimal string alignment), no external crates.
+/// Insertions, deletions, substitutions and adjacent transpositions each cost 1.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// A command the app handles itself instead of running it in `sh -c`
+pub enum Builtin {
+    /// A screenshot tool with no display to capture
+    NoDisplay,
+    /// `cd` moved the working directory here
+    ChangedDir(PathBuf),
+    /// `cd` to something that isn't a directory
+    CdFailed(PathBuf),
+}
 
-    let len_diff = a_bytes.len().abs_diff(b_bytes.len());
+/// The argument of a `cd` command, empty for a bare `cd`.
+/// `cd` must be a whole word: `cdk deploy` is not a `cd`.
+pub fn cd_argument(cmd: &str) -> Option<&str> {
+    let rest = cmd.trim().strip_prefix("cd")?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
 
-    let mismatch = a_bytes
-        .iter()
-        .zip(b_bytes.iter())
-        .filter(|(x, y)| x != y)
-        .count();
+/// Handle what can't usefully run in a subshell: `cd` changes `current_dir`,
+/// and screenshot tools are blocked without a display. None means run `cmd` normally.
+pub fn run_builtin(cmd: &str, current_dir: &mut PathBuf, has_display: bool) -> Option<Builtin> {
+    if !has_display && screenshot::is_screenshot_command(cmd) {
+        return Some(Builtin::NoDisplay);
+    }
 
-    len_diff + mismatch
+    let target = resolve_cd_target(cd_argument(cmd)?, current_dir);
+    if target.is_dir() {
+        *current_dir = target.clone();
+        Some(Builtin::ChangedDir(target))
+    } else {
+        Some(Builtin::CdFailed(target))
+    }
 }
 
-pub fn resolve_cd_target(path: &str, cwd: &PathBuf) -> PathBuf {
+pub fn resolve_cd_target(path: &str, cwd: &Path) -> PathBuf {
     if path.is_empty() || path == "~" {
-        dirs_next::home_dir().unwrap_or_else(|| cwd.clone())
+        dirs_next::home_dir().unwrap_or_else(|| cwd.to_path_buf())
     } else {
         let p = PathBuf::from(path);
         if p.is_absolute() { p } else { cwd.join(p) }
     }
 }
 
-pub fn is_screenshot_command(cmd: &str) -> bool {
-    let c = cmd.to_lowercase();
-    c.contains("scrot")
-        || c.contains("gnome-screenshot")
-        || c.contains("import")
-        || c.contains("screencapture")
+pub fn truncate(s: &str) -> String {
+    if s.len() > MAX_CAPTURE_LEN {
+        let end = s.floor_char_boundary(MAX_CAPTURE_LEN);
+        format!("{}\n... (truncated)", &s[..end])
+    } else {
+        s.to_string()
+    }
 }
 
-fn truncate(s: &str) -> String {
+/// Like `truncate`, but keeps the end: for logs, where the error comes last
+pub fn truncate_tail(s: &str) -> String {
     if s.len() > MAX_CAPTURE_LEN {
-        format!("{}\n... (truncated)", &s[..MAX_CAPTURE_LEN])
+        let start = s.ceil_char_boundary(s.len() - MAX_CAPTURE_LEN);
+        format!("(truncated) ...\n{}", &s[start..])
     } else {
         s.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cd_argument_needs_a_whole_word() {
+        assert_eq!(cd_argument("cd"), Some(""));
+        assert_eq!(cd_argument("cd /tmp"), Some("/tmp"));
+        assert_eq!(cd_argument("  cd\tsrc "), Some("src"));
+        assert_eq!(cd_argument("cdk deploy"), None);
+        assert_eq!(cd_argument("cdrecord -v"), None);
+        assert_eq!(cd_argument("echo cd"), None);
+    }
+
+    #[test]
+    fn truncate_tail_keeps_the_end() {
+        let log = format!("{}\nERROR: disk full", "ok\n".repeat(1000));
+        let kept = truncate_tail(&log);
+        assert!(kept.starts_with("(truncated) ..."));
+        assert!(kept.ends_with("ERROR: disk full"));
+        assert_eq!(truncate_tail("short"), "short");
+    }
+
+    /// An empty directory of its own for a test, under the system temp dir
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ai-terminal-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn edit_distance_counts_single_edits() {
+        assert_eq!(edit_distance("inndex.html", "index.html"), 1);
+        assert_eq!(edit_distance("hanlder", "handler"), 1);
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("index.hmtl", "index.html"), 1);
+        assert_eq!(edit_distance("cat", "car"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn max_typos_grows_with_length() {
+        assert_eq!(max_typos("ls"), 1);
+        assert_eq!(max_typos("main"), 2);
+        assert_eq!(max_typos("handler.rs"), 3);
+    }
+
+    #[test]
+    fn correct_path_fixes_each_segment() {
+        let dir = scratch("correct-path");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/handler.rs"), "").unwrap();
+        fs::write(dir.join("index.html"), "").unwrap();
+
+        assert_eq!(correct_path("src/hanlder.rs", &dir), vec!["src/handler.rs"]);
+        assert_eq!(correct_path("scr/handler.rs", &dir), vec!["src/handler.rs"]);
+        assert_eq!(correct_path("inndex.html", &dir), vec!["index.html"]);
+        // A path that exists needs no correction
+        assert!(correct_path("src/handler.rs", &dir).is_empty());
+        // Only the last component may be a file
+        assert!(correct_path("index.htm/x", &dir).is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn correct_path_ranks_and_caps_candidates() {
+        let dir = scratch("correct-rank");
+        for name in ["dxtx", "daat"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        // Closest first: a transposition before two substitutions
+        assert_eq!(correct_path("data", &dir), vec!["daat", "dxtx"]);
+
+        for name in ["data1", "data2", "data3", "data4", "data5", "data6"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let found = correct_path("data", &dir);
+        assert_eq!(found.len(), MAX_SUGGESTIONS);
+        assert_eq!(found, ["daat", "data1", "data2", "data3", "data4"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,401 @@
+/// A lexical piece of a shell command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A word with quotes and escapes removed
+    Word(String),
+    /// `|`, `||`, `&&`, `;`, `&`
+    Operator(String),
+    /// `>`, `>>`, `<`, `2>`, `2>&1`, `&>` ...
+    Redirect(String),
+}
+
+/// One simple command between control operators
+#[derive(Debug, Clone, Default)]
+pub struct Segment {
+    pub words: Vec<String>,
+    /// (operator, target) pairs such as (">", "out.txt") or ("2>&1", "")
+    pub redirects: Vec<(String, String)>,
+    /// The operator that ends this segment, if any
+    pub separator: Option<String>,
+}
+
+/// Prefixes that run another program rather than being the program of interest
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "time", "nice", "exec", "command", "builtin",
+];
+
+impl Segment {
+    /// The program actually being run: skips `VAR=value` assignments, wrappers
+    /// like `sudo`/`env` and their flags, and any leading path (`/usr/bin/ls` -> `ls`)
+    pub fn program(&self) -> Option<&str> {
+        let mut words = self.words.iter().map(String::as_str);
+        let mut after_wrapper = false;
+
+        while let Some(word) = words.next() {
+            if is_assignment(word) {
+                continue;
+            }
+            if after_wrapper && word.starts_with('-') {
+                // Wrapper options that take a value: sudo -u USER, nice -n 10 ...
+                if matches!(word, "-u" | "-g" | "-n" | "-C") {
+                    words.next();
+                }
+                continue;
+            }
+            if WRAPPERS.contains(&word) {
+                after_wrapper = true;
+                continue;
+            }
+            return Some(word.rsplit('/').next().unwrap_or(word));
+        }
+        None
+    }
+}
+
+pub fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Split a command line into words, operators and redirections, following
+/// sh quoting rules closely enough for display and analysis (no expansion).
+/// `$(...)` and backticks stay inside the word they appear in.
+pub fn tokenize(cmd: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    let mut chars = cmd.chars().peekable();
+
+    fn flush(tokens: &mut Vec<Token>, word: &mut String, in_word: &mut bool, quoted: &mut bool) {
+        if *in_word {
+            tokens.push(Token::Word(std::mem::take(word)));
+        }
+        *in_word = false;
+        *quoted = false;
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => flush(&mut tokens, &mut word, &mut in_word, &mut quoted),
+            '#' if !in_word => break,
+            '\'' => {
+                in_word = true;
+                quoted = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                quoted = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.peek() {
+                            Some(&n @ ('"' | '\\' | '$' | '`')) => {
+                                word.push(n);
+                                chars.next();
+                            }
+                            _ => word.push('\\'),
+                        },
+                        _ => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(n) = chars.next()
+                    && n != '\n'
+                {
+                    word.push(n);
+                }
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                in_word = true;
+                word.push('$');
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    word.push(c);
+                    match c {
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '`' => {
+                in_word = true;
+                word.push('`');
+                for c in chars.by_ref() {
+                    word.push(c);
+                    if c == '`' {
+                        break;
+                    }
+                }
+            }
+            '>' | '<' => {
+                // A bare fd number right before the arrow belongs to the redirect (2>)
+                let mut op = String::new();
+                if in_word
+                    && !quoted
+                    && !word.is_empty()
+                    && word.chars().all(|c| c.is_ascii_digit())
+                {
+                    op = std::mem::take(&mut word);
+                    in_word = false;
+                } else {
+                    flush(&mut tokens, &mut word, &mut in_word, &mut quoted);
+                }
+                op.push(c);
+                if chars.peek() == Some(&c) {
+                    op.push(c);
+                    chars.next();
+                }
+                // 2>&1 style duplication
+                if chars.peek() == Some(&'&') {
+                    op.push('&');
+                    chars.next();
+                    while let Some(&d) = chars.peek() {
+                        if d.is_ascii_digit() || d == '-' {
+                            op.push(d);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                tokens.push(Token::Redirect(op));
+            }
+            '|' | '&' | ';' => {
+                flush(&mut tokens, &mut word, &mut in_word, &mut quoted);
+                let mut op = c.to_string();
+                if c == '&' && chars.peek() == Some(&'>') {
+                    // &> and &>> redirect both streams
+                    op.push('>');
+                    chars.next();
+                    if chars.peek() == Some(&'>') {
+                        op.push('>');
+                        chars.next();
+                    }
+                    tokens.push(Token::Redirect(op));
+                    continue;
+                }
+                if (c == '|' || c == '&') && chars.peek() == Some(&c) {
+                    op.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Operator(op));
+            }
+            _ => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    flush(&mut tokens, &mut word, &mut in_word, &mut quoted);
+
+    tokens
+}
+
+/// Group tokens into simple commands separated by control operators
+pub fn segments(cmd: &str) -> Vec<Segment> {
+    let mut out = Vec::new();
+    let mut current = Segment::default();
+    let mut tokens = tokenize(cmd).into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(w) => current.words.push(w),
+            Token::Redirect(op) => {
+                // Duplications like 2>&1 carry their own target
+                let target = if op.contains('&') && !op.starts_with('&') {
+                    String::new()
+                } else if let Some(Token::Word(_)) = tokens.peek() {
+                    match tokens.next() {
+                        Some(Token::Word(w)) => w,
+                        _ => String::new(),
+                    }
+                } else {
+                    String::new()
+                };
+                current.redirects.push((op, target));
+            }
+            Token::Operator(op) => {
+                current.separator = Some(op);
+                out.push(std::mem::take(&mut current));
+            }
+        }
+    }
+
+    if !current.words.is_empty() || !current.redirects.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+/// Rebuild a segment as text for display and policy checks
+pub fn segment_text(segment: &Segment) -> String {
+    let mut parts: Vec<String> = segment.words.iter().map(|w| quote(w)).collect();
+    for (op, target) in &segment.redirects {
+        if target.is_empty() {
+            parts.push(op.clone());
+        } else {
+            parts.push(format!("{} {}", op, quote(target)));
+        }
+    }
+    parts.join(" ")
+}
+
+fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+~$()`*?[]{}".contains(c))
+    {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// The program run by each simple command in `cmd`, in order
+pub fn programs(cmd: &str) -> Vec<String> {
+    segments(cmd)
+        .iter()
+        .filter_map(|s| s.program().map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(w: &str) -> Token {
+        Token::Word(w.to_string())
+    }
+
+    fn op(o: &str) -> Token {
+        Token::Operator(o.to_string())
+    }
+
+    #[test]
+    fn quoted_operators_stay_in_the_word() {
+        assert_eq!(
+            tokenize(r#"echo "a && b" 'c | d' ; ls"#),
+            vec![
+                word("echo"),
+                word("a && b"),
+                word("c | d"),
+                op(";"),
+                word("ls")
+            ]
+        );
+        assert_eq!(segments("echo 'x;y' && make").len(), 2);
+    }
+
+    #[test]
+    fn operators_split_segments() {
+        let segs = segments("make && ./run || echo failed; cat log | grep x &");
+        let seps: Vec<_> = segs.iter().map(|s| s.separator.as_deref()).collect();
+        assert_eq!(
+            seps,
+            vec![Some("&&"), Some("||"), Some(";"), Some("|"), Some("&")]
+        );
+        assert_eq!(
+            programs("make && ./run || echo failed"),
+            vec!["make", "run", "echo"]
+        );
+    }
+
+    #[test]
+    fn backslash_escapes() {
+        assert_eq!(tokenize(r"echo a\ b"), vec![word("echo"), word("a b")]);
+        assert_eq!(
+            tokenize(r"echo \&\& \;"),
+            vec![word("echo"), word("&&"), word(";")]
+        );
+        assert_eq!(
+            tokenize(r#"echo "say \"hi\" \n""#),
+            vec![word("echo"), word(r#"say "hi" \n"#)]
+        );
+        // Line continuation disappears
+        assert_eq!(tokenize("ls \\\n-l"), vec![word("ls"), word("-l")]);
+    }
+
+    #[test]
+    fn assignments_and_wrappers_are_skipped() {
+        assert_eq!(programs("LANG=C FOO=1 sort file"), vec!["sort"]);
+        assert_eq!(programs("sudo -u postgres psql"), vec!["psql"]);
+        assert_eq!(programs("sudo env PATH=/x /usr/bin/ls -la"), vec!["ls"]);
+        assert_eq!(programs("nice -n 10 make"), vec!["make"]);
+        assert!(is_assignment("A_1=x"));
+        assert!(!is_assignment("1A=x"));
+        assert!(!is_assignment("--opt=x"));
+    }
+
+    #[test]
+    fn redirects_keep_their_targets() {
+        let segs = segments("cmd 2>&1 >out.txt 2> err &>> all");
+        assert_eq!(segs.len(), 1);
+        assert_eq!(
+            segs[0].redirects,
+            vec![
+                ("2>&1".to_string(), String::new()),
+                (">".to_string(), "out.txt".to_string()),
+                ("2>".to_string(), "err".to_string()),
+                ("&>>".to_string(), "all".to_string()),
+            ]
+        );
+        assert_eq!(segs[0].words, vec!["cmd"]);
+    }
+
+    #[test]
+    fn substitutions_stay_whole() {
+        assert_eq!(
+            tokenize("echo $(date; id) `uname -a`"),
+            vec![word("echo"), word("$(date; id)"), word("`uname -a`")]
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes_take_the_rest() {
+        assert_eq!(
+            tokenize("echo 'abc def"),
+            vec![word("echo"), word("abc def")]
+        );
+        assert_eq!(
+            tokenize(r#"echo "a && b"#),
+            vec![word("echo"), word("a && b")]
+        );
+        assert_eq!(segments("grep 'x | y").len(), 1);
+    }
+
+    #[test]
+    fn comments_and_empty_input() {
+        assert_eq!(tokenize("ls # list files"), vec![word("ls")]);
+        assert_eq!(tokenize("echo a#b"), vec![word("echo"), word("a#b")]);
+        assert!(segments("").is_empty());
+        assert!(programs("   ").is_empty());
+    }
+
+    #[test]
+    fn segment_text_requotes() {
+        let segs = segments("echo 'hello world' > 'my file'");
+        assert_eq!(segment_text(&segs[0]), "echo 'hello world' > 'my file'");
+    }
+}
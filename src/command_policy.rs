@@ -1,15 +1,24 @@
-#[derive(Debug)]
+use colored::*;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CommandRisk {
     Safe,
     Caution,
     Dangerous,
 }
-pub struct CommandResult {
-    pub success: bool,
-    pub exit_code: i32,
-    pub stdout: String,
-    pub stderr: String,
+
+impl fmt::Display for CommandRisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            CommandRisk::Safe => "safe".green(),
+            CommandRisk::Caution => "caution".yellow(),
+            CommandRisk::Dangerous => "dangerous".red().bold(),
+        };
+        write!(f, "{}", label)
+    }
 }
+
 pub fn classify_command(cmd: &str) -> CommandRisk {
     let cmd = cmd.trim().to_lowercase();
 
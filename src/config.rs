@@ -0,0 +1,146 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// The file `load` read, where `set` writes back to
+static ACTIVE: OnceLock<PathBuf> = OnceLock::new();
+/// Variables already set before the config file was read, which it cannot change
+static PRESET: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// `<config dir>/ai-terminal/config.env`
+pub fn default_path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|d| d.join("ai-terminal").join("config.env"))
+}
+
+/// Load settings into the environment: `.env` from the working directory first,
+/// then the config file. Variables that are already set always win.
+/// An explicit `--config` path must exist; the default one is optional.
+pub fn load(explicit: Option<&Path>) -> Result<Option<PathBuf>, String> {
+    let _ = dotenvy::dotenv();
+    let _ = PRESET.set(
+        env::vars_os()
+            .filter_map(|(k, _)| k.into_string().ok())
+            .collect(),
+    );
+
+    let path = match explicit {
+        Some(p) => {
+            if !p.is_file() {
+                return Err(format!("config file not found: {}", p.display()));
+            }
+            p.to_path_buf()
+        }
+        None => match default_path() {
+            Some(p) if p.is_file() => p,
+            _ => return Ok(None),
+        },
+    };
+
+    dotenvy::from_path(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let _ = ACTIVE.set(path.clone());
+    Ok(Some(path))
+}
+
+/// Whether `key` came from the environment or `.env`, so a value saved with
+/// `set` will not take effect on the next start
+pub fn overridden(key: &str) -> bool {
+    PRESET.get().is_some_and(|preset| preset.contains(key))
+}
+
+/// Save `key=value` in the config file in use (the default one if none was
+/// loaded), replacing an earlier value; `None` removes the key.
+/// Returns the file written.
+pub fn set(key: &str, value: Option<&str>) -> Result<PathBuf, String> {
+    let path = match ACTIVE.get() {
+        Some(p) => p.clone(),
+        None => default_path().ok_or("no config directory on this system")?,
+    };
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let text = replace_key(&existing, key, value);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// `existing` with the first `KEY=` or `export KEY=` line set to `value` in
+/// place and any later ones dropped; appended if the key is new
+fn replace_key(existing: &str, key: &str, value: Option<&str>) -> String {
+    let prefix = format!("{}=", key);
+    let entry = value.map(|value| {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("{}\"{}\"", prefix, escaped)
+    });
+
+    let mut lines = Vec::new();
+    let mut replaced = false;
+    for line in existing.lines() {
+        let trimmed = line.trim_start();
+        let assignment = trimmed
+            .strip_prefix("export ")
+            .map_or(trimmed, str::trim_start);
+        if !assignment.starts_with(&prefix) {
+            lines.push(line.to_string());
+            continue;
+        }
+        if !replaced {
+            let export = if assignment.len() < trimmed.len() {
+                "export "
+            } else {
+                ""
+            };
+            lines.extend(entry.as_ref().map(|e| format!("{}{}", export, e)));
+            replaced = true;
+        }
+    }
+    if !replaced {
+        lines.extend(entry);
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_in_place() {
+        let existing = "# audio\nAI_AUDIO_DEVICE=\"old\"\nAI_TTS=1\n";
+        assert_eq!(
+            replace_key(existing, "AI_AUDIO_DEVICE", Some("hw:1")),
+            "# audio\nAI_AUDIO_DEVICE=\"hw:1\"\nAI_TTS=1\n"
+        );
+    }
+
+    #[test]
+    fn keeps_export_and_drops_duplicates() {
+        let existing = "export AI_AUDIO_DEVICE=old\nAI_TTS=1\nAI_AUDIO_DEVICE=older\n";
+        assert_eq!(
+            replace_key(existing, "AI_AUDIO_DEVICE", Some("say \"hi\"")),
+            "export AI_AUDIO_DEVICE=\"say \\\"hi\\\"\"\nAI_TTS=1\n"
+        );
+    }
+
+    #[test]
+    fn removes_or_appends() {
+        let existing = "AI_TTS=1\nexport AI_AUDIO_DEVICE=old\n";
+        assert_eq!(replace_key(existing, "AI_AUDIO_DEVICE", None), "AI_TTS=1\n");
+        assert_eq!(
+            replace_key("AI_TTS=1", "AI_AUDIO_DEVICE", Some("hw:1")),
+            "AI_TTS=1\nAI_AUDIO_DEVICE=\"hw:1\"\n"
+        );
+        // A longer key with the same start is not touched
+        assert_eq!(
+            replace_key("AI_AUDIO_DEVICE_X=1", "AI_AUDIO_DEVICE", None),
+            "AI_AUDIO_DEVICE_X=1\n"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::sys;
+use colored::*;
+use rustyline::DefaultEditor;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Edit a command on a prefilled prompt line. Returns None if the user aborts or clears it.
+pub fn edit_inline(cmd: &str) -> Option<String> {
+    let mut editor = DefaultEditor::new().ok()?;
+    let edited = editor.readline_with_initial("edit> ", (cmd, "")).ok()?;
+    let edited = edited.trim();
+
+    if edited.is_empty() {
+        None
+    } else {
+        Some(edited.to_string())
+    }
+}
+
+/// Edit a command in `$VISUAL` / `$EDITOR` (falls back to `vi`) through a temp file
+pub fn edit_external(cmd: &str) -> Option<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+
+    // A fresh file in our private dir; create_new refuses anything planted there
+    let path = match sys::private_temp_dir() {
+        Ok(dir) => {
+            let stamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            dir.join(format!("cmd-{}-{}.sh", std::process::id(), stamp))
+        }
+        Err(e) => {
+            println!("{} {}", "Could not create temp file:".red(), e);
+            return None;
+        }
+    };
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut f| writeln!(f, "{}", cmd));
+    if let Err(e) = written {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            let _ = fs::remove_file(&path);
+        }
+        println!("{} {}", "Could not create temp file:".red(), e);
+        return None;
+    }
+
+    // Run through sh so EDITOR values with arguments ("code --wait") work
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+
+    let edited = match status {
+        Ok(s) if s.success() => fs::read_to_string(&path).ok(),
+        Ok(s) => {
+            println!("{} {}", "Editor exited with".red(), s);
+            None
+        }
+        Err(e) => {
+            println!("{} {}", "Failed to launch editor:".red(), e);
+            None
+        }
+    };
+    let _ = fs::remove_file(&path);
+
+    let edited = edited?;
+    let edited = edited.trim();
+    if edited.is_empty() {
+        None
+    } else {
+        Some(edited.to_string())
+    }
+}
+
+/// Parse a rejection that carries a reason, e.g. `n: use rsync instead`
+pub fn rejection_reason(answer: &str) -> Option<String> {
+    let (head, reason) = answer.split_once(':')?;
+    let head = head.trim();
+    let reason = reason.trim();
+
+    if (head.eq_ignore_ascii_case("n") || head.eq_ignore_ascii_case("no")) && !reason.is_empty() {
+        Some(reason.to_string())
+    } else {
+        None
+    }
+}
+
+/// Print a prompt and read a single key press, without waiting for Enter.
+/// Falls back to the first character of a line when the terminal can't be put
+/// into cbreak mode. Enter, Escape and Ctrl-C come back as `'\n'`.
+pub fn ask_key(prompt: &str) -> char {
+    let Ok(mut tty) = File::options().read(true).write(true).open("/dev/tty") else {
+        return ask(prompt).chars().next().unwrap_or('\n');
+    };
+    let Some(saved) = stty(&tty, &["-g"]) else {
+        return ask(prompt).chars().next().unwrap_or('\n');
+    };
+
+    print!("{}", prompt.bold());
+    io::stdout().flush().unwrap();
+
+    // -isig so Ctrl-C arrives as a byte instead of killing us with echo off
+    let mut buf = [0u8; 8];
+    let read = match stty(&tty, &["-icanon", "-echo", "-isig", "min", "1"]) {
+        Some(_) => tty.read(&mut buf).unwrap_or(0),
+        None => 0,
+    };
+    stty(&tty, &[saved.trim()]);
+
+    // Escape sequences (arrow keys) arrive whole and count as "ignore"
+    let key = match &buf[..read] {
+        [b] if b.is_ascii_graphic() => (*b as char).to_ascii_lowercase(),
+        _ => '\n',
+    };
+    println!("{}", key.to_string().trim());
+    key
+}
+
+fn stty(tty: &File, args: &[&str]) -> Option<String> {
+    let out = Command::new("stty")
+        .args(args)
+        .stdin(tty.try_clone().ok()?)
+        .output()
+        .ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+/// Print a prompt and read one trimmed answer from the user.
+/// When stdin is piped content, the answer comes from `/dev/tty` instead;
+/// without a terminal the answer is empty, which every caller treats as "no".
+pub fn ask(prompt: &str) -> String {
+    print!("{}", prompt.bold());
+    io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    if io::stdin().is_terminal() {
+        io::stdin().read_line(&mut answer).unwrap();
+    } else if let Ok(tty) = File::open("/dev/tty") {
+        let _ = BufReader::new(tty).read_line(&mut answer);
+    } else {
+        println!();
+    }
+    answer.trim().to_string()
+}
@@ -0,0 +1,209 @@
+use crate::cmdline::{self, Segment};
+use crate::command_policy::{self, CommandRisk};
+use crate::groq::{GroqClient, Message};
+use colored::*;
+use std::collections::HashMap;
+
+/// One thing to explain: a program, option, argument, redirection or operator
+struct Part {
+    text: String,
+    kind: &'static str,
+    /// Description we can give without the model
+    local: Option<String>,
+    depth: usize,
+}
+
+/// Break `command` into its parts, explain each one and show the policy verdict.
+/// Nothing is executed. Falls back to local descriptions if the model is unavailable.
+pub async fn explain(groq_client: &GroqClient, command: &str) {
+    let segments = cmdline::segments(command);
+    if segments.is_empty() {
+        println!("{}", "Nothing to explain.".dimmed());
+        return;
+    }
+
+    let parts = collect_parts(&segments);
+    let explanations = ask_model(groq_client, command, &parts).await;
+
+    println!("{} {}", "Command:".bold(), command.cyan());
+    for (i, part) in parts.iter().enumerate() {
+        let text = explanations
+            .get(&(i + 1))
+            .cloned()
+            .or_else(|| part.local.clone())
+            .unwrap_or_default();
+        let indent = "  ".repeat(part.depth + 1);
+        let label = match part.kind {
+            "program" => part.text.green().bold(),
+            "pipe" | "operator" => part.text.yellow().bold(),
+            "redirect" => part.text.magenta(),
+            _ => part.text.cyan(),
+        };
+        println!(
+            "{}{}  {}  {}",
+            indent,
+            label,
+            format!("[{}]", part.kind).dimmed(),
+            text
+        );
+    }
+
+    // Check each simple command on its own, plus the whole line for patterns
+    // that span operators such as fork bombs
+    let mut overall = command_policy::classify_command(command);
+    for segment in &segments {
+        let text = cmdline::segment_text(segment);
+        let risk = command_policy::classify_command(&text);
+        if risk != CommandRisk::Safe {
+            println!("  {} {}", format!("{}:", risk).bold(), text);
+        }
+        overall = overall.max(risk);
+    }
+    println!("{} {}", "Risk:".bold(), overall);
+}
+
+fn collect_parts(segments: &[Segment]) -> Vec<Part> {
+    let mut parts = Vec::new();
+
+    for segment in segments {
+        let program = segment.program();
+        let mut seen_program = false;
+
+        for word in &segment.words {
+            let (kind, depth) =
+                if !seen_program && Some(word.rsplit('/').next().unwrap_or(word)) == program {
+                    seen_program = true;
+                    ("program", 0)
+                } else if !seen_program && cmdline::is_assignment(word) {
+                    ("variable", 0)
+                } else if !seen_program {
+                    ("wrapper", 0)
+                } else if word.starts_with('-') && word.len() > 1 {
+                    ("option", 1)
+                } else {
+                    ("argument", 1)
+                };
+            parts.push(Part {
+                text: word.clone(),
+                kind,
+                local: None,
+                depth,
+            });
+        }
+
+        for (op, target) in &segment.redirects {
+            let text = if target.is_empty() {
+                op.clone()
+            } else {
+                format!("{} {}", op, target)
+            };
+            parts.push(Part {
+                text,
+                kind: "redirect",
+                local: Some(describe_redirect(op, target)),
+                depth: 1,
+            });
+        }
+
+        if let Some(sep) = &segment.separator {
+            parts.push(Part {
+                text: sep.clone(),
+                kind: if sep == "|" { "pipe" } else { "operator" },
+                local: Some(describe_operator(sep).to_string()),
+                depth: 0,
+            });
+        }
+    }
+
+    parts
+}
+
+fn describe_operator(op: &str) -> &'static str {
+    match op {
+        "|" => "send the output of the left command into the right one",
+        "||" => "run the next command only if the previous one failed",
+        "&&" => "run the next command only if the previous one succeeded",
+        ";" => "run the next command afterwards, whatever happened",
+        "&" => "run the previous command in the background",
+        _ => "shell operator",
+    }
+}
+
+fn describe_redirect(op: &str, target: &str) -> String {
+    let stream = if op.starts_with('2') {
+        "errors (stderr)"
+    } else if op.starts_with('&') {
+        "output and errors"
+    } else {
+        "output (stdout)"
+    };
+
+    if op.contains(">&") {
+        let to = op.rsplit('&').next().unwrap_or("");
+        return match to {
+            "1" => format!("send {} to the same place as stdout", stream),
+            "2" => format!("send {} to the same place as stderr", stream),
+            "-" => format!("close {}", stream),
+            _ => format!("duplicate {} onto descriptor {}", stream, to),
+        };
+    }
+
+    if op.ends_with("<<") {
+        format!("read input from the here-document ending at `{}`", target)
+    } else if op.ends_with('<') {
+        format!("read input from {}", target)
+    } else if op.ends_with(">>") {
+        format!("append {} to {}", stream, target)
+    } else {
+        format!("write {} to {} (overwrites it)", stream, target)
+    }
+}
+
+/// Ask the model for one short line per numbered part
+async fn ask_model(
+    groq_client: &GroqClient,
+    command: &str,
+    parts: &[Part],
+) -> HashMap<usize, String> {
+    let listing: String = parts
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("{}. {} ({})\n", i + 1, p.text, p.kind))
+        .collect();
+
+    let messages = vec![
+        Message {
+            role: "system".into(),
+            content: "You explain shell commands to a user who wants to know what they do \
+                before running them. For every numbered part, reply with exactly one line \
+                `<number>: <short explanation in the context of this command>`. \
+                No other text."
+                .into(),
+        },
+        Message {
+            role: "user".into(),
+            content: format!("Command: {}\nParts:\n{}", command, listing).into(),
+        },
+    ];
+
+    let reply = match groq_client.chat(messages).await {
+        Ok(r) => r,
+        Err(e) => {
+            println!(
+                "{} {}",
+                "Model unavailable, showing structure only:".yellow(),
+                e
+            );
+            return HashMap::new();
+        }
+    };
+
+    reply
+        .lines()
+        .filter_map(|line| {
+            let (n, text) = line.trim().split_once(':')?;
+            let n = n.trim().trim_start_matches('#').parse().ok()?;
+            Some((n, text.trim().to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,431 @@
+use crate::cmd;
+use crate::cmdline;
+use std::{
+    env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::UNIX_EPOCH,
+};
+
+const MAX_MATCHES: usize = 3;
+/// Programs known to print help and exit on `--help`. Anything else is only
+/// looked up in its man page: some tools ignore `--help` and do their work.
+const HELP_SAFE: &[&str] = &[
+    "apt",
+    "apt-get",
+    "awk",
+    "cargo",
+    "cat",
+    "chmod",
+    "chown",
+    "cp",
+    "curl",
+    "cut",
+    "date",
+    "df",
+    "diff",
+    "docker",
+    "du",
+    "find",
+    "gh",
+    "git",
+    "go",
+    "grep",
+    "gzip",
+    "head",
+    "kubectl",
+    "ln",
+    "ls",
+    "make",
+    "mkdir",
+    "mv",
+    "npm",
+    "pip",
+    "pip3",
+    "ps",
+    "rg",
+    "rm",
+    "rsync",
+    "rustc",
+    "rustup",
+    "sed",
+    "sort",
+    "systemctl",
+    "tail",
+    "tar",
+    "touch",
+    "uniq",
+    "unzip",
+    "wc",
+    "wget",
+    "yarn",
+    "zip",
+];
+
+/// Programs in `HELP_SAFE` whose first argument is a subcommand with its own help
+const WITH_SUBCOMMANDS: &[&str] = &[
+    "apt",
+    "cargo",
+    "docker",
+    "gh",
+    "git",
+    "go",
+    "kubectl",
+    "npm",
+    "rustup",
+    "systemctl",
+];
+
+/// A corrected flag: the fixed commands and the help lines that describe them
+pub struct FlagFix {
+    pub commands: Vec<String>,
+    pub excerpt: String,
+}
+
+/// Options the program itself lists in its help, with the line each came from
+struct HelpOptions {
+    source: String,
+    options: Vec<(String, usize)>,
+    lines: Vec<String>,
+}
+
+/// Extract the rejected flag from common option parser errors:
+///   ls: unrecognized option '--recursiv'
+///   grep: invalid option -- 'z'
+///   Error: unknown flag: --forse
+///   error: unknown option `forse'        (git)
+///   error: unexpected argument '--forse' found        (clap)
+///   error: unrecognized arguments: --forse        (argparse)
+///   find: unknown predicate `-nmae'
+pub fn extract_bad_flag(stderr: &str) -> Option<String> {
+    const MARKERS: &[&str] = &[
+        "unrecognized option",
+        "unrecognised option",
+        "invalid option",
+        "illegal option",
+        "unknown shorthand flag:",
+        "unknown flag:",
+        "unknown option",
+        "unknown switch",
+        "unexpected argument",
+        "unrecognized arguments:",
+        "unknown predicate",
+    ];
+
+    for line in stderr.lines() {
+        for marker in MARKERS {
+            let Some(pos) = line.find(marker) else {
+                continue;
+            };
+            let rest = line[pos + marker.len()..].trim_start_matches([':', ' ', '-']);
+            let Some(word) = rest.split_whitespace().next() else {
+                continue;
+            };
+            let name = word.trim_matches(|c| matches!(c, '\'' | '"' | '`' | ',' | ':'));
+            let name = name.split('=').next().unwrap_or(name);
+            if name.is_empty() {
+                continue;
+            }
+
+            // git and getopt drop the dashes: `forse', -- 'z'
+            return Some(if name.starts_with('-') {
+                name.to_string()
+            } else if name.chars().count() == 1 {
+                format!("-{}", name)
+            } else {
+                format!("--{}", name)
+            });
+        }
+    }
+    None
+}
+
+/// Closest valid flags for an `unrecognized option` style failure, taken from the
+/// failing program's `--help` output or man page
+pub fn suggest(cmd: &str, stderr: &str) -> Option<FlagFix> {
+    let bad = extract_bad_flag(stderr)?;
+    let name = bad.trim_start_matches('-');
+    // A single letter is as close to every other short flag; nothing to rank
+    if name.chars().count() < 2 {
+        return None;
+    }
+
+    // 1. Find the simple command that used the flag
+    let segment = cmdline::segments(cmd).into_iter().find(|s| {
+        s.words
+            .iter()
+            .any(|w| w == &bad || w.starts_with(&format!("{}=", bad)))
+    })?;
+    let program = segment.program()?.to_string();
+
+    // 2. Subcommand help first (`git push --help`), then the program's own
+    let sources = help_sources(&program, &segment.words);
+
+    // git, argparse and cobra print the usage along with the error
+    let mut helps: Vec<HelpOptions> = parse_options("the error output".into(), stderr)
+        .into_iter()
+        .collect();
+    helps.extend(
+        sources
+            .iter()
+            .filter_map(|args| help_options(&program, args)),
+    );
+    if helps.is_empty() {
+        helps.extend(man_options(&program));
+    }
+
+    // 3. Rank the listed options by distance to the rejected one
+    for help in helps {
+        let mut matches: Vec<(usize, &str, usize)> = help
+            .options
+            .iter()
+            .filter_map(|(option, line)| {
+                let candidate = option.trim_start_matches('-');
+                if candidate.chars().count() < 2 {
+                    return None;
+                }
+                let d = cmd::edit_distance(name, candidate);
+                (d <= cmd::max_typos(name)).then_some((d, option.as_str(), *line))
+            })
+            .collect();
+        if matches.is_empty() {
+            continue;
+        }
+        matches.sort();
+        matches.dedup_by(|a, b| a.1 == b.1);
+        matches.truncate(MAX_MATCHES);
+
+        let commands = matches
+            .iter()
+            .map(|(_, option, _)| cmd.replacen(bad.as_str(), option, 1))
+            .collect();
+
+        let mut lines: Vec<usize> = matches.iter().map(|(_, _, line)| *line).collect();
+        lines.sort();
+        lines.dedup();
+        let mut excerpt = format!("from {}:", help.source);
+        for i in lines {
+            excerpt.push_str("\n  ");
+            excerpt.push_str(help.lines[i].trim());
+        }
+
+        return Some(FlagFix { commands, excerpt });
+    }
+
+    None
+}
+
+/// The `--help` invocations worth running for `program` used with `words`.
+/// Only `--help` is tried, and only for programs in HELP_SAFE: `-h` means
+/// something else to many programs. The next word is taken as a subcommand
+/// only for WITH_SUBCOMMANDS; elsewhere it may be a host or a script.
+fn help_sources(program: &str, words: &[String]) -> Vec<Vec<String>> {
+    if !HELP_SAFE.contains(&program) {
+        return Vec::new();
+    }
+
+    let subcommand = words
+        .iter()
+        .skip_while(|w| w.rsplit('/').next() != Some(program))
+        .nth(1)
+        .filter(|_| WITH_SUBCOMMANDS.contains(&program))
+        .filter(|w| {
+            !w.starts_with('-') && w.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    let mut sources = Vec::new();
+    if let Some(sub) = subcommand {
+        sources.push(vec![sub.clone(), "--help".into()]);
+    }
+    sources.push(vec!["--help".into()]);
+    sources
+}
+
+/// Options listed by `program args...`, cached per binary
+fn help_options(program: &str, args: &[String]) -> Option<HelpOptions> {
+    let source = format!("{} {}", program, args.join(" "));
+    let text = cached(program, &source, || {
+        let out = Command::new(program)
+            .args(args)
+            .env("PAGER", "cat")
+            .env("MANPAGER", "cat")
+            .env("GIT_PAGER", "cat")
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+        let mut text = String::from_utf8_lossy(&out.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&out.stderr));
+        Some(text)
+    })?;
+    parse_options(source, &text)
+}
+
+fn man_options(program: &str) -> Option<HelpOptions> {
+    let source = format!("man {}", program);
+    let text = cached(program, &source, || {
+        let out = Command::new("man")
+            .arg(program)
+            .env("MANPAGER", "cat")
+            .env("MANWIDTH", "100")
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+        out.status
+            .success()
+            .then(|| strip_overstrike(&String::from_utf8_lossy(&out.stdout)))
+    })?;
+    parse_options(source, &text)
+}
+
+/// Collect every flag that starts an option line, e.g.
+/// `  -r, -R, --recursive   copy directories recursively`
+fn parse_options(source: String, text: &str) -> Option<HelpOptions> {
+    let lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let mut options = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if !line.trim_start().starts_with('-') {
+            continue;
+        }
+        // The option list ends where the description starts (two spaces)
+        let head = line.trim_start().split("  ").next().unwrap_or("");
+        for word in head.split([' ', ',', '|']) {
+            // `--[no-]force` stands for both spellings
+            let (word, negatable) = match word.strip_prefix("--[no-]") {
+                Some(rest) => (rest, true),
+                None => (word, false),
+            };
+            let flag: String = word
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                .collect();
+            if negatable {
+                options.push((format!("--{}", flag), i));
+                options.push((format!("--no-{}", flag), i));
+            } else if flag.starts_with('-') && !flag.trim_start_matches('-').is_empty() {
+                options.push((flag, i));
+            }
+        }
+    }
+
+    if options.is_empty() {
+        None
+    } else {
+        Some(HelpOptions {
+            source,
+            options,
+            lines,
+        })
+    }
+}
+
+/// Help text for `source`, read from the cache or produced by `fetch` and stored.
+/// The key covers the resolved binary and its modification time, so upgrades
+/// are picked up without running the program.
+fn cached(program: &str, source: &str, fetch: impl FnOnce() -> Option<String>) -> Option<String> {
+    let binary = resolve(program)?;
+    let modified = fs::metadata(&binary)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    let mut hasher = DefaultHasher::new();
+    (binary.as_path(), modified, source).hash(&mut hasher);
+    let path = dirs_next::cache_dir().map(|d| {
+        d.join("ai-terminal").join("flags").join(format!(
+            "{}-{:016x}.txt",
+            program,
+            hasher.finish()
+        ))
+    });
+
+    if let Some(path) = &path
+        && let Ok(text) = fs::read_to_string(path)
+    {
+        return Some(text);
+    }
+
+    let text = fetch()?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    if let Some(path) = &path
+        && let Some(parent) = path.parent()
+        && fs::create_dir_all(parent).is_ok()
+    {
+        let _ = fs::write(path, &text);
+    }
+    Some(text)
+}
+
+/// Full path of `program` on `PATH`
+fn resolve(program: &str) -> Option<PathBuf> {
+    let path = env::var("PATH").unwrap_or_default();
+    env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|p| p.is_file())
+}
+
+/// Remove the `c\bc` bold and `_\bc` underline sequences some `man` builds emit
+fn strip_overstrike(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\u{8}' {
+            out.pop();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_flag_from_each_parser() {
+        let cases = [
+            ("ls: unrecognized option '--recursiv'", "--recursiv"),
+            ("grep: invalid option -- 'z'", "-z"),
+            ("Error: unknown flag: --forse", "--forse"),
+            ("error: unknown option `forse'", "--forse"),
+            ("error: unexpected argument '--forse' found", "--forse"),
+            ("find: unknown predicate `-nmae'", "-nmae"),
+        ];
+        for (stderr, flag) in cases {
+            assert_eq!(
+                extract_bad_flag(stderr).as_deref(),
+                Some(flag),
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn help_runs_only_for_known_programs() {
+        let words = |cmd: &str| cmdline::segments(cmd).remove(0).words;
+        let help = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            help_sources("git", &words("git push --forse")),
+            vec![help(&["push", "--help"]), help(&["--help"])]
+        );
+        assert_eq!(
+            help_sources("ls", &words("ls src --recursiv")),
+            vec![help(&["--help"])]
+        );
+        assert!(help_sources("ssh", &words("ssh host ls --recursiv")).is_empty());
+        assert!(help_sources("python3", &words("python3 myscript --verbos")).is_empty());
+    }
+
+    #[test]
+    fn bare_marker_line_does_not_end_the_search() {
+        let stderr = "warning: unknown option\nls: unrecognized option '--recursiv'";
+        assert_eq!(extract_bad_flag(stderr).as_deref(), Some("--recursiv"));
+    }
+}
@@ -1,68 +1,825 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::sync::{Arc, Mutex};
+use cpal::{FromSample, I24, Sample, SampleFormat, SizedSample, U24};
+use std::{
+    env, fmt,
+    io::Cursor,
+    ops::Range,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Whisper works on 16 kHz mono; anything more is wasted upload
+pub const TARGET_RATE: u32 = 16_000;
+
+/// Length of one VAD analysis frame
+const FRAME_MS: u32 = 30;
+/// Audio kept from before speech was detected, so the first syllable isn't clipped
+const PRE_ROLL_MS: u32 = 300;
+/// Audio kept after the last speech frame
+const TAIL_MS: u32 = 200;
+
+const DEFAULT_SILENCE_MS: u64 = 1200;
+const DEFAULT_MAX_SECS: u64 = 30;
+const DEFAULT_THRESHOLD: f32 = 0.02;
+/// Zero crossings per second above which a quieter frame counts as a fricative
+const FRICATIVE_ZCR: f32 = 4000.0;
+
+/// When voice activity detection starts and stops a recording
+#[derive(Debug, Clone, Copy)]
+pub struct VadConfig {
+    /// Stop after this much silence following speech
+    pub silence: Duration,
+    /// Stop regardless after this long
+    pub max_duration: Duration,
+    /// RMS level (0.0-1.0) above which a frame counts as voiced speech
+    pub threshold: f32,
+}
+
+impl VadConfig {
+    /// Reads `AI_VAD_SILENCE_MS`, `AI_VAD_MAX_SECS` and `AI_VAD_THRESHOLD`, falling back
+    /// to defaults for unset, malformed or negative values
+    pub fn from_env() -> Self {
+        let parse = |key: &str| {
+            let value = env::var(key).ok()?;
+            match value.trim().parse::<f64>() {
+                Ok(v) if v.is_finite() && v >= 0.0 => Some(v),
+                _ => {
+                    eprintln!("Ignoring {}={}: expected a number, 0 or more", key, value);
+                    None
+                }
+            }
+        };
+        Self {
+            silence: Duration::from_millis(
+                parse("AI_VAD_SILENCE_MS").map_or(DEFAULT_SILENCE_MS, |v| v as u64),
+            ),
+            max_duration: Duration::from_secs(
+                parse("AI_VAD_MAX_SECS").map_or(DEFAULT_MAX_SECS, |v| v as u64),
+            ),
+            threshold: parse("AI_VAD_THRESHOLD").map_or(DEFAULT_THRESHOLD, |v| v as f32),
+        }
+    }
+}
+
+/// Where the listener is, reported with every level update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VadState {
+    /// No speech yet
+    Waiting,
+    /// Speech in the current frame
+    Speaking,
+    /// Speech seen before, silence now
+    Pausing,
+}
+
+/// Samples at or above this are treated as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Loudness of a recording, for diagnosing a microphone
+#[derive(Debug, Clone, Copy)]
+pub struct Levels {
+    /// Highest absolute sample, 0.0-1.0
+    pub peak: f32,
+    pub rms: f32,
+    /// Samples at full scale
+    pub clipped: usize,
+    pub duration: Duration,
+}
+
+/// An input device as `:audio devices` lists it
+pub struct InputDevice {
+    pub host: String,
+    /// Stable identifier such as `alsa:hw:CARD=PCH,DEV=0`; what gets saved
+    pub id: String,
+    /// Human-readable description
+    pub name: String,
+    pub is_default: bool,
+    /// Supported configurations, e.g. "44100-48000 Hz, 1-2 ch, i16/f32"
+    pub configs: Vec<String>,
+}
+
+/// Every input device of every available audio host (ALSA, JACK, ...)
+pub fn input_devices() -> Vec<InputDevice> {
+    let mut found = Vec::new();
+    for (host, devices) in all_input_devices() {
+        let default = cpal::host_from_id(host)
+            .ok()
+            .and_then(|h| h.default_input_device())
+            .map(|d| device_id(&d));
+        for device in devices {
+            let id = device_id(&device);
+            found.push(InputDevice {
+                host: host.name().to_string(),
+                is_default: default.as_deref() == Some(id.as_str()),
+                name: device_label(&device),
+                configs: supported_configs(&device),
+                id,
+            });
+        }
+    }
+    found
+}
+
+/// The id of the input device `wanted` refers to, see `find_input_device`
+pub fn resolve_input_device(wanted: &str) -> Result<String, anyhow::Error> {
+    find_input_device(wanted).map(|d| device_id(&d))
+}
+
+/// Find an input device by id or name: an exact (case-insensitive) match,
+/// or else the only device whose id or name contains `wanted`
+fn find_input_device(wanted: &str) -> Result<cpal::Device, anyhow::Error> {
+    let wanted = wanted.to_lowercase();
+    let mut partial = Vec::new();
+    for (_, devices) in all_input_devices() {
+        for device in devices {
+            let id = device_id(&device).to_lowercase();
+            let name = device_label(&device).to_lowercase();
+            if id == wanted || name == wanted {
+                return Ok(device);
+            }
+            if id.contains(&wanted) || name.contains(&wanted) {
+                partial.push(device);
+            }
+        }
+    }
+
+    match partial.len() {
+        0 => anyhow::bail!("No input device matches '{}' (see :audio devices)", wanted),
+        1 => Ok(partial.remove(0)),
+        _ => {
+            let ids: Vec<String> = partial.iter().map(device_id).collect();
+            anyhow::bail!("'{}' matches several devices: {}", wanted, ids.join(", "))
+        }
+    }
+}
+
+fn all_input_devices() -> Vec<(cpal::HostId, Vec<cpal::Device>)> {
+    cpal::available_hosts()
+        .into_iter()
+        .filter_map(|id| {
+            let devices = cpal::host_from_id(id).ok()?.input_devices().ok()?;
+            Some((id, devices.collect()))
+        })
+        .collect()
+}
+
+/// Supported configs folded together, since plugin devices often list every
+/// channel count from 1 to 32 in every sample format separately
+fn supported_configs(device: &cpal::Device) -> Vec<String> {
+    let Ok(configs) = device.supported_input_configs() else {
+        return Vec::new();
+    };
+
+    // (rates, format) -> (min channels, max channels), in first-seen order
+    let mut grouped: Vec<(String, SampleFormat, u16, u16)> = Vec::new();
+    for c in configs {
+        let rates = if c.min_sample_rate() == c.max_sample_rate() {
+            c.min_sample_rate().to_string()
+        } else {
+            format!("{}-{}", c.min_sample_rate(), c.max_sample_rate())
+        };
+        match grouped
+            .iter_mut()
+            .find(|(r, f, _, _)| *r == rates && *f == c.sample_format())
+        {
+            Some((_, _, min, max)) => {
+                *min = (*min).min(c.channels());
+                *max = (*max).max(c.channels());
+            }
+            None => grouped.push((rates, c.sample_format(), c.channels(), c.channels())),
+        }
+    }
+
+    // Then list the formats sharing rates and channels on one line
+    let mut lines: Vec<(String, Vec<String>)> = Vec::new();
+    for (rates, format, min, max) in grouped {
+        let channels = if min == max {
+            min.to_string()
+        } else {
+            format!("{}-{}", min, max)
+        };
+        let key = format!("{} Hz, {} ch", rates, channels);
+        match lines.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, formats)) => formats.push(format.to_string()),
+            None => lines.push((key, vec![format.to_string()])),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(key, formats)| format!("{}, {}", key, formats.join("/")))
+        .collect()
+}
+
+fn device_id(device: &cpal::Device) -> String {
+    device
+        .id()
+        .map(|id| id.to_string())
+        .unwrap_or_else(|_| device_label(device))
+}
+
+fn device_label(device: &cpal::Device) -> String {
+    device
+        .description()
+        .map(|d| d.name().to_string())
+        .unwrap_or_else(|_| "unnamed input".into())
+}
+
+/// What the input actually delivers, before downmixing and resampling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, {} ch, {}",
+            self.sample_rate, self.channels, self.sample_format
+        )
+    }
+}
+
+enum Source {
+    Device(cpal::Stream),
+    #[cfg(test)]
+    Synthetic {
+        stop: Arc<std::sync::atomic::AtomicBool>,
+        thread: thread::JoinHandle<()>,
+    },
+}
 
 pub struct AudioRecorder {
-    stream: cpal::Stream,
+    source: Source,
+    /// Mono samples at the input's own rate
     buffer: Arc<Mutex<Vec<f32>>>,
+    name: String,
+    format: InputFormat,
+    /// Part of `buffer` to keep, set by `listen`
+    keep: Option<Range<usize>>,
 }
 
 impl AudioRecorder {
-    pub fn start() -> Result<Self, anyhow::Error> {
-        let host = cpal::default_host();
-
-        // 1. Get Default Input Device
-        let device = host
-            .default_input_device()
-            .ok_or_else(|| anyhow::anyhow!("No input device found"))?;
+    /// Record from the input device named `device`, or the default one, in
+    /// whatever format it offers
+    pub fn start(device: Option<&str>) -> Result<Self, anyhow::Error> {
+        // 1. Get the chosen or default Input Device
+        let device = match device {
+            Some(wanted) => find_input_device(wanted)?,
+            None => cpal::default_host()
+                .default_input_device()
+                .ok_or_else(|| anyhow::anyhow!("No input device found"))?,
+        };
+        let name = device_label(&device);
 
-        // 2. Configure Stream (Standard mono, 16kHz is usually best for Whisper)
+        // 2. Take the device's own format; we convert afterwards
         let config = device.default_input_config()?;
-        let stream_config: cpal::StreamConfig = config.clone().into();
+        let format = InputFormat {
+            sample_rate: config.sample_rate(),
+            channels: config.channels(),
+            sample_format: config.sample_format(),
+        };
+        let stream_config: cpal::StreamConfig = config.into();
 
         let buffer = Arc::new(Mutex::new(Vec::new()));
-        let buffer_clone = buffer.clone();
-
-        // 3. Build the Input Stream
-        let stream = device.build_input_stream(
-            &stream_config,
-            move |data: &[f32], _: &_| {
-                // Low-latency callback: simply push data to our buffer
-                if let Ok(mut b) = buffer_clone.lock() {
-                    b.extend_from_slice(data);
-                }
-            },
-            |err| eprintln!("Audio stream error: {}", err),
-            None, // None = blocking, but cpal handles this internal timeout usually
-        )?;
+
+        // 3. Build the Input Stream for the device's sample type
+        let b = buffer.clone();
+        let stream = match format.sample_format {
+            SampleFormat::I8 => build::<i8>(&device, &stream_config, b)?,
+            SampleFormat::I16 => build::<i16>(&device, &stream_config, b)?,
+            SampleFormat::I24 => build::<I24>(&device, &stream_config, b)?,
+            SampleFormat::I32 => build::<i32>(&device, &stream_config, b)?,
+            SampleFormat::I64 => build::<i64>(&device, &stream_config, b)?,
+            SampleFormat::U8 => build::<u8>(&device, &stream_config, b)?,
+            SampleFormat::U16 => build::<u16>(&device, &stream_config, b)?,
+            SampleFormat::U24 => build::<U24>(&device, &stream_config, b)?,
+            SampleFormat::U32 => build::<u32>(&device, &stream_config, b)?,
+            SampleFormat::U64 => build::<u64>(&device, &stream_config, b)?,
+            SampleFormat::F32 => build::<f32>(&device, &stream_config, b)?,
+            SampleFormat::F64 => build::<f64>(&device, &stream_config, b)?,
+            other => anyhow::bail!("Unsupported sample format: {}", other),
+        };
 
         stream.play()?;
 
-        Ok(Self { stream, buffer })
+        Ok(Self {
+            source: Source::Device(stream),
+            buffer,
+            name,
+            format,
+            keep: None,
+        })
     }
 
-    pub fn stop_and_save(self, file_path: &str) -> Result<(), anyhow::Error> {
-        // Stop stream logic happens when 'stream' is dropped
-        drop(self.stream);
+    /// A recorder fed with a 440 Hz tone as interleaved frames in `format`,
+    /// delivered in 10 ms chunks like a device callback
+    #[cfg(test)]
+    fn synthetic(format: InputFormat) -> Self {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let InputFormat {
+            sample_rate,
+            channels,
+            sample_format,
+        } = format;
+
+        let thread = {
+            let buffer = buffer.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let frames = (sample_rate / 100) as usize;
+                let mut t = 0usize;
+                while !stop.load(Ordering::Relaxed) {
+                    let mut chunk = Vec::with_capacity(frames * channels as usize);
+                    for _ in 0..frames {
+                        let phase = t as f32 * 440.0 * std::f32::consts::TAU / sample_rate as f32;
+                        chunk.extend(std::iter::repeat_n(phase.sin() * 0.25, channels as usize));
+                        t += 1;
+                    }
+                    match sample_format {
+                        SampleFormat::I16 => {
+                            push_frames(&buffer, channels, &convert::<i16>(&chunk))
+                        }
+                        SampleFormat::U8 => push_frames(&buffer, channels, &convert::<u8>(&chunk)),
+                        SampleFormat::F32 => push_frames(&buffer, channels, &chunk),
+                        other => panic!("no synthetic {} source", other),
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            })
+        };
+
+        Self {
+            source: Source::Synthetic { stop, thread },
+            buffer,
+            name: "synthetic tone".into(),
+            format,
+            keep: None,
+        }
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn input_format(&self) -> InputFormat {
+        self.format
+    }
+
+    /// Block until an utterance is over: waits for speech, then stops after
+    /// `config.silence` of quiet or at `config.max_duration`. `on_level` gets the
+    /// RMS level of the latest frame for a meter. Returns false if nobody spoke.
+    pub fn listen(&mut self, config: &VadConfig, mut on_level: impl FnMut(f32, VadState)) -> bool {
+        let rate = self.format.sample_rate as usize;
+        let ms = |ms: u64| rate * ms as usize / 1000;
+        let frame = ms(FRAME_MS as u64).max(1);
+        let silence = ms(config.silence.as_millis() as u64);
+        let max = ms(config.max_duration.as_millis() as u64);
+        // A device that stops delivering must not hang us
+        let deadline = Instant::now() + config.max_duration + Duration::from_secs(2);
+
+        let mut pos = 0;
+        let mut start = None;
+        let mut last_speech = 0;
+
+        loop {
+            thread::sleep(Duration::from_millis(FRAME_MS as u64));
+
+            // Latest frame's level and whether it was speech
+            let mut latest = None;
+            let total = {
+                let data = self.buffer.lock().unwrap();
+                while pos + frame <= data.len() {
+                    let (rms, zcr) = analyze(&data[pos..pos + frame], self.format.sample_rate);
+                    let speech = is_speech(rms, zcr, config.threshold);
+                    if speech {
+                        start.get_or_insert(pos.saturating_sub(ms(PRE_ROLL_MS as u64)));
+                        last_speech = pos + frame;
+                    }
+                    latest = Some((rms, speech));
+                    pos += frame;
+                }
+                data.len()
+            };
+
+            if let Some((level, speech)) = latest {
+                let state = match (start, speech) {
+                    (None, _) => VadState::Waiting,
+                    (Some(_), true) => VadState::Speaking,
+                    (Some(_), false) => VadState::Pausing,
+                };
+                on_level(level, state);
+            }
+
+            let done = match start {
+                Some(_) => pos.saturating_sub(last_speech) >= silence || total >= max,
+                None => total >= max,
+            };
+            if done || Instant::now() >= deadline {
+                self.keep = start.map(|s| s..(last_speech + ms(TAIL_MS as u64)).min(total));
+                return start.is_some();
+            }
+        }
+    }
 
+    /// Peak and RMS level of everything recorded so far, at the input's own rate
+    pub fn levels(&self) -> Levels {
         let data = self.buffer.lock().unwrap();
+        let peak = data.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let rms = if data.is_empty() {
+            0.0
+        } else {
+            (data.iter().map(|s| s * s).sum::<f32>() / data.len() as f32).sqrt()
+        };
+        Levels {
+            peak,
+            rms,
+            clipped: data.iter().filter(|s| s.abs() >= CLIP_LEVEL).count(),
+            duration: Duration::from_secs_f64(data.len() as f64 / self.format.sample_rate as f64),
+        }
+    }
+
+    /// Stop recording and return the audio as mono samples at `TARGET_RATE`,
+    /// trimmed to the utterance if `listen` found one
+    pub fn stop(self) -> Vec<f32> {
+        match self.source {
+            // Stop stream logic happens when 'stream' is dropped
+            Source::Device(stream) => drop(stream),
+            #[cfg(test)]
+            Source::Synthetic { stop, thread } => {
+                stop.store(true, Ordering::Relaxed);
+                let _ = thread.join();
+            }
+        }
+
+        let data = std::mem::take(&mut *self.buffer.lock().unwrap());
+        let data = match self.keep {
+            Some(range) => &data[range.start.min(data.len())..range.end.min(data.len())],
+            None => &data[..],
+        };
+        resample(data, self.format.sample_rate, TARGET_RATE)
+    }
+
+    /// Stop recording and encode the audio as a 16-bit mono WAV file in memory
+    pub fn stop_to_wav(self) -> Result<Vec<u8>, anyhow::Error> {
+        let samples = self.stop();
 
-        // 4. Save to WAV (Required for API)
+        // 4. Encode as WAV (Required for API)
         let spec = hound::WavSpec {
             channels: 1,
-            sample_rate: 44100, // Or whatever the device default was
+            sample_rate: TARGET_RATE,
             bits_per_sample: 16,
             sample_format: hound::SampleFormat::Int,
         };
 
-        let mut writer = hound::WavWriter::create(file_path, spec)?;
-
-        // Convert f32 samples (-1.0 to 1.0) to i16 for WAV
-        for &sample in data.iter() {
-            let amplitude = i16::MAX as f32;
-            writer.write_sample((sample * amplitude) as i16)?;
+        let mut wav = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut wav, spec)?;
+        for sample in samples {
+            writer.write_sample(sample.clamp(-1.0, 1.0).to_sample::<i16>())?;
         }
 
         writer.finalize()?;
-        Ok(())
+        Ok(wav.into_inner())
+    }
+}
+
+fn build<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: Arc<Mutex<Vec<f32>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels;
+    device.build_input_stream(
+        config,
+        // Low-latency callback: downmix and push to our buffer
+        move |data: &[T], _: &_| push_frames(&buffer, channels, data),
+        |err| eprintln!("Audio stream error: {}", err),
+        None,
+    )
+}
+
+/// Decode a WAV file into mono samples and its sample rate
+pub fn decode_wav(bytes: &[u8]) -> Result<(Vec<f32>, u32), anyhow::Error> {
+    let bytes = fix_stream_lengths(bytes);
+    let mut reader = hound::WavReader::new(Cursor::new(bytes))?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample.clamp(1, 32) - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let mono = Mutex::new(Vec::new());
+    push_frames(&mono, spec.channels, &interleaved);
+    Ok((mono.into_inner().unwrap(), spec.sample_rate))
+}
+
+/// Encoders writing to a pipe (espeak --stdout, streamed API responses) can't
+/// seek back to fill in the lengths, so they put placeholders in the header.
+/// Replace them with what actually arrived.
+fn fix_stream_lengths(bytes: &[u8]) -> Vec<u8> {
+    let mut out = bytes.to_vec();
+    if out.len() < 12 || &out[0..4] != b"RIFF" || &out[8..12] != b"WAVE" {
+        return out;
+    }
+    let riff_len = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+
+    let mut block_align = 1;
+    let mut pos = 12;
+    while pos + 8 <= out.len() {
+        let id = &out[pos..pos + 4];
+        let declared = u32::from_le_bytes(out[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let available = out.len() - pos - 8;
+        if id == b"fmt " && available >= 14 {
+            block_align = u16::from_le_bytes([out[pos + 20], out[pos + 21]]).max(1) as usize;
+        }
+        if id == b"data" {
+            let len = declared.min(available) / block_align * block_align;
+            out[pos + 4..pos + 8].copy_from_slice(&(len as u32).to_le_bytes());
+            break;
+        }
+        // Chunks are padded to an even length
+        pos += 8 + declared + declared % 2;
+    }
+    out
+}
+
+/// Play mono samples on the default output device. Blocks until playback
+/// ends or `interrupted` returns true.
+pub fn play(
+    samples: &[f32],
+    sample_rate: u32,
+    interrupted: impl Fn() -> bool,
+) -> Result<(), anyhow::Error> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| anyhow::anyhow!("No output device found"))?;
+    let config = device.default_output_config()?;
+    let samples = Arc::new(resample(samples, sample_rate, config.sample_rate()));
+    let pos = Arc::new(AtomicUsize::new(0));
+
+    let format = config.sample_format();
+    let stream_config: cpal::StreamConfig = config.into();
+    let (s, p) = (samples.clone(), pos.clone());
+    let stream = match format {
+        SampleFormat::I8 => build_output::<i8>(&device, &stream_config, s, p)?,
+        SampleFormat::I16 => build_output::<i16>(&device, &stream_config, s, p)?,
+        SampleFormat::I24 => build_output::<I24>(&device, &stream_config, s, p)?,
+        SampleFormat::I32 => build_output::<i32>(&device, &stream_config, s, p)?,
+        SampleFormat::I64 => build_output::<i64>(&device, &stream_config, s, p)?,
+        SampleFormat::U8 => build_output::<u8>(&device, &stream_config, s, p)?,
+        SampleFormat::U16 => build_output::<u16>(&device, &stream_config, s, p)?,
+        SampleFormat::U24 => build_output::<U24>(&device, &stream_config, s, p)?,
+        SampleFormat::U32 => build_output::<u32>(&device, &stream_config, s, p)?,
+        SampleFormat::U64 => build_output::<u64>(&device, &stream_config, s, p)?,
+        SampleFormat::F32 => build_output::<f32>(&device, &stream_config, s, p)?,
+        SampleFormat::F64 => build_output::<f64>(&device, &stream_config, s, p)?,
+        other => anyhow::bail!("Unsupported sample format: {}", other),
+    };
+    stream.play()?;
+
+    while pos.load(Ordering::Relaxed) < samples.len() {
+        if interrupted() {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    // Let the device drain its last buffer before the stream is dropped
+    thread::sleep(Duration::from_millis(150));
+    Ok(())
+}
+
+fn build_output<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: Arc<Vec<f32>>,
+    pos: Arc<AtomicUsize>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_output_stream(
+        config,
+        // Copy each mono sample to every channel, silence once we run out
+        move |data: &mut [T], _: &_| {
+            let mut i = pos.load(Ordering::Relaxed);
+            for frame in data.chunks_mut(channels) {
+                let sample = samples.get(i).copied().unwrap_or(0.0);
+                frame.fill(sample.to_sample::<T>());
+                i = (i + 1).min(samples.len());
+            }
+            pos.store(i, Ordering::Relaxed);
+        },
+        |err| eprintln!("Audio stream error: {}", err),
+        None,
+    )
+}
+
+/// Average each interleaved frame down to one mono `f32` sample
+fn push_frames<T>(buffer: &Mutex<Vec<f32>>, channels: u16, data: &[T])
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    let channels = channels.max(1) as usize;
+    if let Ok(mut b) = buffer.lock() {
+        b.extend(data.chunks(channels).map(|frame| {
+            frame.iter().map(|s| s.to_sample::<f32>()).sum::<f32>() / frame.len() as f32
+        }));
+    }
+}
+
+/// RMS level and zero-crossing rate (crossings per second) of one frame
+/// recorded at `sample_rate`
+fn analyze(frame: &[f32], sample_rate: u32) -> (f32, f32) {
+    let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt();
+    let crossings = frame
+        .windows(2)
+        .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+        .count();
+    (
+        rms,
+        crossings as f32 * sample_rate as f32 / frame.len() as f32,
+    )
+}
+
+/// Voiced speech is loud with few zero crossings; fricatives (s, f, sh) are
+/// quieter but cross often. Quiet broadband hiss stays below both.
+fn is_speech(rms: f32, zcr: f32, threshold: f32) -> bool {
+    rms >= threshold || (rms >= threshold / 2.0 && zcr > FRICATIVE_ZCR)
+}
+
+/// Convert mono audio between sample rates. Downsampling averages the input
+/// samples behind each output sample, a crude low-pass that keeps speech clean;
+/// upsampling interpolates linearly.
+fn resample(input: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || input.is_empty() {
+        return input.to_vec();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let len = (input.len() as f64 / ratio) as usize;
+
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            if ratio > 1.0 {
+                let start = pos as usize;
+                let end = (((i + 1) as f64 * ratio) as usize).clamp(start + 1, input.len());
+                input[start..end].iter().sum::<f32>() / (end - start) as f32
+            } else {
+                let idx = pos as usize;
+                let frac = (pos - idx as f64) as f32;
+                let next = input.get(idx + 1).copied().unwrap_or(input[idx]);
+                input[idx] * (1.0 - frac) + next * frac
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn convert<T: Sample + FromSample<f32>>(samples: &[f32]) -> Vec<T> {
+    samples.iter().map(|s| s.to_sample::<T>()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_scales_length_by_rate() {
+        let second = vec![0.5; 48_000];
+        let down = resample(&second, 48_000, TARGET_RATE);
+        assert_eq!(down.len(), 16_000);
+        assert!(down.iter().all(|s| (s - 0.5).abs() < 1e-6));
+
+        let up = resample(&vec![0.5; 8_000], 8_000, TARGET_RATE);
+        assert_eq!(up.len(), 16_000);
+        assert!(up.iter().all(|s| (s - 0.5).abs() < 1e-6));
+
+        assert_eq!(
+            resample(&[0.1, 0.2], TARGET_RATE, TARGET_RATE),
+            vec![0.1, 0.2]
+        );
+        assert!(resample(&[], 44_100, TARGET_RATE).is_empty());
+    }
+
+    #[test]
+    fn zero_crossings_do_not_depend_on_the_sample_rate() {
+        // A 5 kHz hiss-like tone, quiet enough to need the crossing test
+        let tone = |rate: u32| -> Vec<f32> {
+            (0..rate as usize * FRAME_MS as usize / 1000)
+                .map(|t| (t as f32 * 5000.0 * std::f32::consts::TAU / rate as f32).sin() * 0.02)
+                .collect()
+        };
+        for rate in [16_000, 44_100, 48_000] {
+            let (rms, zcr) = analyze(&tone(rate), rate);
+            assert!((9000.0..11000.0).contains(&zcr), "{} Hz: {}", rate, zcr);
+            assert!(is_speech(rms, zcr, DEFAULT_THRESHOLD), "{} Hz", rate);
+        }
+    }
+
+    #[test]
+    fn push_frames_downmixes_to_mono() {
+        let buffer = Mutex::new(Vec::new());
+        push_frames(&buffer, 2, &[1.0f32, 0.0, -0.5, -0.5]);
+        push_frames(&buffer, 1, &[0.25f32]);
+        assert_eq!(*buffer.lock().unwrap(), vec![0.5, -0.5, 0.25]);
+
+        let buffer = Mutex::new(Vec::new());
+        push_frames(&buffer, 2, &[i16::MAX, i16::MAX, i16::MIN, i16::MIN]);
+        let mono = buffer.into_inner().unwrap();
+        assert!((mono[0] - 1.0).abs() < 1e-3 && (mono[1] + 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn every_input_format_becomes_16k_mono_wav() {
+        for (sample_format, sample_rate, channels) in [
+            (SampleFormat::I16, 48_000, 2),
+            (SampleFormat::U8, 44_100, 1),
+            (SampleFormat::F32, 8_000, 2),
+        ] {
+            let format = InputFormat {
+                sample_rate,
+                channels,
+                sample_format,
+            };
+            let recorder = AudioRecorder::synthetic(format);
+            assert_eq!(recorder.input_format(), format);
+            thread::sleep(Duration::from_millis(100));
+            let wav = recorder.stop_to_wav().unwrap();
+
+            let mut reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+            let spec = reader.spec();
+            assert_eq!((spec.channels, spec.sample_rate), (1, TARGET_RATE));
+            assert_eq!(spec.bits_per_sample, 16);
+
+            let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+            assert!(!samples.is_empty(), "{}", format);
+            // The 0.25 tone survives conversion, resampling and encoding
+            let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap() as f32 / 32768.0;
+            assert!((0.15..0.3).contains(&peak), "{}: peak {}", format, peak);
+        }
+    }
+
+    fn wav(channels: u16, samples: &[i16]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: 22_050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut out = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut out, spec).unwrap();
+        for &s in samples {
+            writer.write_sample(s).unwrap();
+        }
+        writer.finalize().unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn decode_wav_downmixes_and_reports_the_rate() {
+        let (samples, rate) = decode_wav(&wav(2, &[16384, 0, -16384, -16384])).unwrap();
+        assert_eq!(rate, 22_050);
+        assert_eq!(samples, vec![0.25, -0.5]);
+    }
+
+    #[test]
+    fn streamed_wav_lengths_are_repaired() {
+        let mut bytes = wav(1, &[1000, 2000, 3000]);
+        // What espeak --stdout writes before it knows the length
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        // and a stream cut off halfway through a sample
+        bytes.push(0x7f);
+
+        let fixed = fix_stream_lengths(&bytes);
+        assert_eq!(&fixed[4..8], &(bytes.len() as u32 - 8).to_le_bytes());
+        assert_eq!(&fixed[40..44], &6u32.to_le_bytes());
+
+        let (samples, _) = decode_wav(&bytes).unwrap();
+        assert_eq!(samples.len(), 3);
+
+        // Anything that isn't a RIFF/WAVE file is passed through untouched
+        assert_eq!(fix_stream_lengths(b"not a wav"), b"not a wav".to_vec());
     }
 }
@@ -1,13 +1,14 @@
-use base64::{Engine as _, engine::general_purpose};
 use reqwest::{Client, multipart};
-use std::time::Duration;
+use std::{sync::Mutex, time::Duration};
 use thiserror::Error;
-use tokio::fs::File;
-use tokio_util::codec::{BytesCodec, FramedRead};
 
-use super::types::Message;
+use super::types::{Content, ContentPart, Message};
 
 const GROQ_API_URL: &str = "https://api.groq.com/openai/v1/chat/completions";
+/// Used for conversations with images unless `AI_VISION_MODEL` says otherwise
+pub const DEFAULT_VISION_MODEL: &str = "meta-llama/llama-4-scout-17b-16e-instruct";
+/// Groq accepts at most five images per request
+const MAX_IMAGES: usize = 5;
 
 #[derive(Debug, Error)]
 pub enum GroqError {
@@ -18,10 +19,20 @@ pub enum GroqError {
     Api(String),
 }
 
+/// Token counts accumulated from the `usage` field of chat responses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
 pub struct GroqClient {
     http: Client,
     api_key: String,
     model: String,
+    vision_model: String,
+    usage: Mutex<TokenUsage>,
 }
 
 impl GroqClient {
@@ -33,12 +44,45 @@ impl GroqClient {
                 .unwrap_or_default(),
             api_key: api_key.into(),
             model: model.into(),
+            vision_model: DEFAULT_VISION_MODEL.into(),
+            usage: Mutex::new(TokenUsage::default()),
         }
     }
 
-    pub async fn chat(&self, messages: Vec<Message>) -> Result<String, GroqError> {
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.model = model.into();
+    }
+
+    pub fn vision_model(&self) -> &str {
+        &self.vision_model
+    }
+
+    pub fn set_vision_model(&mut self, model: impl Into<String>) {
+        self.vision_model = model.into();
+    }
+
+    pub fn usage(&self) -> TokenUsage {
+        *self.usage.lock().unwrap()
+    }
+
+    /// Send the conversation to the chat model. While the conversation still holds
+    /// an image it goes to the vision model instead, so later steps can keep
+    /// looking at it; only the most recent `MAX_IMAGES` are sent.
+    pub async fn chat(&self, mut messages: Vec<Message>) -> Result<String, GroqError> {
+        drop_old_images(&mut messages);
+        let has_images = messages.iter().any(Message::has_image);
+        let model = if has_images {
+            &self.vision_model
+        } else {
+            &self.model
+        };
+
         let payload = serde_json::json!({
-            "model": self.model,
+            "model": model,
             "messages": messages,
             "temperature": 0.7,
             "max_tokens": 512
@@ -53,84 +97,48 @@ impl GroqClient {
             .await?;
 
         if !res.status().is_success() {
-            return Err(GroqError::Api(res.text().await?));
+            let status = res.status();
+            let body = res.text().await?;
+            return Err(self.api_error(status, body, has_images));
         }
 
         let json: serde_json::Value = res.json().await?;
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.requests += 1;
+            usage.prompt_tokens += json["usage"]["prompt_tokens"].as_u64().unwrap_or(0);
+            usage.completion_tokens += json["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+        }
         Ok(json["choices"][0]["message"]["content"]
             .as_str()
             .unwrap_or("")
             .to_string())
     }
 
-    pub async fn analyze_image_file(
-        &self,
-        image_path: &str,
-        user_prompt: &str,
-    ) -> Result<String, GroqError> {
-        // 1️⃣ Load image bytes
-        let image_bytes = std::fs::read(image_path)
-            .map_err(|e| GroqError::Api(format!("Failed to read image: {}", e)))?;
-
-        // 2️⃣ Encode as base64
-        let encoded = general_purpose::STANDARD.encode(&image_bytes);
-
-        // 3️⃣ Build vision-aware input
-        // Use the Responses API (vision models) instead of chat completions
-        let payload = serde_json::json!({
-            "model": self.model,
-            "input": [
-                {
-                    "role": "user",
-                    "content": [
-                        {
-                            "type": "text",
-                            "text": user_prompt
-                        },
-                        {
-                            "type": "input_image",
-                            "image": {
-                                "data": encoded,
-                                "mime": "image/png"
-                            }
-                        }
-                    ]
-                }
-            ]
-        });
-
-        // 4️⃣ Send request
-        let url = format!("{}/responses", GROQ_API_URL);
-        let res = self
-            .http
-            .post(&url)
-            .bearer_auth(&self.api_key)
-            .json(&payload)
-            .send()
-            .await?;
-
-        if !res.status().is_success() {
-            return Err(GroqError::Api(res.text().await?));
+    /// The API's own error message; a text-only model given images answers 400
+    /// and mentions the image content, which gets a hint about `AI_VISION_MODEL`
+    fn api_error(&self, status: reqwest::StatusCode, body: String, has_images: bool) -> GroqError {
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|j| j["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or(body);
+
+        let lower = message.to_lowercase();
+        if has_images
+            && status == reqwest::StatusCode::BAD_REQUEST
+            && (lower.contains("image") || lower.contains("content must be a string"))
+        {
+            return GroqError::Api(format!(
+                "model {} cannot read images ({}). Set AI_VISION_MODEL to a vision model such as {}",
+                self.vision_model, message, DEFAULT_VISION_MODEL
+            ));
         }
-
-        let json: serde_json::Value = res.json().await?;
-        // Extract text out of the first output entry (model answer)
-        let answer = json["output"][0]["content"][0]["text"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-
-        Ok(answer)
+        GroqError::Api(message)
     }
 
-    pub async fn transcribe_audio(&self, file_path: &str) -> Result<String, GroqError> {
+    /// Transcribe a recording held in memory; `wav` is a complete WAV file
+    pub async fn transcribe_audio(&self, wav: Vec<u8>) -> Result<String, GroqError> {
         // 1. Prepare File Upload
-        let file = File::open(file_path)
-            .await
-            .map_err(|e| GroqError::Api(format!("File not found: {}", e)))?;
-
-        let stream = FramedRead::new(file, BytesCodec::new());
-        let file_part = multipart::Part::stream(reqwest::Body::wrap_stream(stream))
+        let file_part = multipart::Part::bytes(wav)
             .file_name("recording.wav")
             .mime_str("audio/wav")
             .unwrap();
@@ -158,3 +166,59 @@ impl GroqClient {
         Ok(json["text"].as_str().unwrap_or("").to_string())
     }
 }
+
+/// Replace all but the newest `MAX_IMAGES` images with a short note
+fn drop_old_images(messages: &mut [Message]) {
+    let mut kept = 0;
+    for message in messages.iter_mut().rev() {
+        let Content::Parts(parts) = &mut message.content else {
+            continue;
+        };
+        for part in parts.iter_mut().rev() {
+            if let ContentPart::ImageUrl { .. } = part {
+                if kept < MAX_IMAGES {
+                    kept += 1;
+                } else {
+                    *part = ContentPart::Text {
+                        text: "[earlier image omitted]".into(),
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groq::types::ImageUrl;
+
+    fn image_message(n: usize) -> Message {
+        let image = ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: format!("data:image/png;base64,{}", n),
+            },
+        };
+        Message::with_image("user", format!("COMMAND_OUTPUT: {}", n), image)
+    }
+
+    #[test]
+    fn only_the_newest_images_are_kept() {
+        let mut messages: Vec<Message> = (0..MAX_IMAGES + 2).map(image_message).collect();
+        messages.push(Message {
+            role: "user".into(),
+            content: "COMMAND_OUTPUT: no image".into(),
+        });
+        drop_old_images(&mut messages);
+
+        let kept: Vec<bool> = messages.iter().map(Message::has_image).collect();
+        assert_eq!(kept[..2], [false, false]);
+        assert!(kept[2..MAX_IMAGES + 2].iter().all(|&k| k));
+        assert!(
+            messages[0]
+                .content
+                .text()
+                .contains("[earlier image omitted]")
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use base64::{Engine as _, engine::general_purpose};
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
+use std::io::Cursor;
+
+use super::client::GroqError;
+
+/// Groq rejects base64 image payloads over 4 MB
+const MAX_PAYLOAD_BYTES: usize = 4_000_000;
+/// Largest raw image whose `data:` URL fits in `MAX_PAYLOAD_BYTES`: base64 turns
+/// every 3 bytes into 4, after the `data:image/jpeg;base64,` prefix (about 2.99 MB)
+const MAX_IMAGE_BYTES: usize = (MAX_PAYLOAD_BYTES - "data:image/jpeg;base64,".len()) * 3 / 4;
+/// Longest side sent to the model. Larger images cost more tokens without
+/// making text on screenshots any easier to read.
+const MAX_DIMENSION: u32 = 2048;
+
+/// Identify PNG, JPEG, WebP and GIF data by their magic bytes
+pub fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, rest @ ..] if rest.starts_with(b"WEBP") => {
+            Some("image/webp")
+        }
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        _ => None,
+    }
+}
+
+/// Turn raw image bytes into a `data:` URL for an `image_url` content part,
+/// downscaling images that are too large or too big to upload
+pub fn data_url(bytes: &[u8]) -> Result<String, GroqError> {
+    let mime = sniff_mime(bytes).ok_or_else(|| {
+        GroqError::Api("Unsupported image format (expected PNG, JPEG, WebP or GIF)".into())
+    })?;
+
+    let (mime, bytes) = fit(mime, bytes)?;
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+/// Re-encode the image if it exceeds `MAX_DIMENSION` or `MAX_IMAGE_BYTES`.
+/// Screenshots stay PNG so text stays sharp; everything else becomes JPEG.
+fn fit(mime: &'static str, bytes: &[u8]) -> Result<(&'static str, Vec<u8>), GroqError> {
+    let decoded = image::load_from_memory(bytes)
+        .map_err(|e| GroqError::Api(format!("Failed to decode image: {}", e)))?;
+
+    let too_wide = decoded.width().max(decoded.height()) > MAX_DIMENSION;
+    if !too_wide && bytes.len() <= MAX_IMAGE_BYTES {
+        return Ok((mime, bytes.to_vec()));
+    }
+
+    let mut image = if too_wide {
+        decoded.resize(MAX_DIMENSION, MAX_DIMENSION, FilterType::Triangle)
+    } else {
+        decoded
+    };
+
+    let (mime, format) = if mime == "image/png" {
+        ("image/png", ImageFormat::Png)
+    } else {
+        ("image/jpeg", ImageFormat::Jpeg)
+    };
+
+    // Halve until it fits; a photo rarely needs more than one or two rounds
+    loop {
+        // JPEG has no alpha channel
+        let encodable = if format == ImageFormat::Jpeg {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        } else {
+            image.clone()
+        };
+
+        let mut out = Cursor::new(Vec::new());
+        encodable
+            .write_to(&mut out, format)
+            .map_err(|e| GroqError::Api(format!("Failed to re-encode image: {}", e)))?;
+        let out = out.into_inner();
+
+        if out.len() <= MAX_IMAGE_BYTES || image.width() <= 256 {
+            return Ok((mime, out));
+        }
+        image = image.resize(image.width() / 2, image.height() / 2, FilterType::Triangle);
+    }
+}
@@ -1,6 +1,11 @@
 mod audio;
 mod client;
+mod image;
 mod types;
-pub use audio::AudioRecorder;
+pub use audio::{
+    AudioRecorder, TARGET_RATE, VadConfig, VadState, decode_wav, input_devices, play,
+    resolve_input_device,
+};
 pub use client::GroqClient;
-pub use types::Message;
+pub use image::sniff_mime;
+pub use types::{Content, ContentPart, Message};
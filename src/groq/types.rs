@@ -1,9 +1,104 @@
 use serde::{Deserialize, Serialize};
 
+use super::client::GroqError;
+use super::image;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    pub content: Content,
+}
+
+/// Message content in either of the chat-completions forms: a plain string,
+/// or an array of typed parts so text and images can share one message
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+impl Message {
+    /// A user message carrying `text` followed by an image
+    pub fn with_image(role: &str, text: impl Into<String>, image: ContentPart) -> Self {
+        Message {
+            role: role.into(),
+            content: Content::Parts(vec![ContentPart::Text { text: text.into() }, image]),
+        }
+    }
+
+    pub fn has_image(&self) -> bool {
+        match &self.content {
+            Content::Text(_) => false,
+            Content::Parts(parts) => parts
+                .iter()
+                .any(|p| matches!(p, ContentPart::ImageUrl { .. })),
+        }
+    }
+}
+
+impl Content {
+    /// Put `text` in front of the message text, ahead of any images
+    pub fn prepend(&mut self, text: &str) {
+        match self {
+            Content::Text(existing) => existing.insert_str(0, text),
+            Content::Parts(parts) => match parts.first_mut() {
+                Some(ContentPart::Text { text: existing }) => existing.insert_str(0, text),
+                _ => parts.insert(0, ContentPart::Text { text: text.into() }),
+            },
+        }
+    }
+
+    /// The text parts joined by newlines; images show up as `[image]`
+    pub fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts
+                .iter()
+                .map(|p| match p {
+                    ContentPart::Text { text } => text.as_str(),
+                    ContentPart::ImageUrl { .. } => "[image]",
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::Text(text.to_string())
+    }
+}
+
+impl ContentPart {
+    /// An `image_url` part holding the image as a data URL (PNG, JPEG, WebP or GIF).
+    /// Large images are downscaled first.
+    pub fn image(bytes: &[u8]) -> Result<Self, GroqError> {
+        Ok(ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: image::data_url(bytes)?,
+            },
+        })
+    }
 }
 
 // #[derive(Debug)]
@@ -12,3 +107,47 @@ pub struct Message {
 //     Caution,
 //     Dangerous,
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn plain_string_content_is_text() {
+        let message: Message =
+            serde_json::from_value(json!({"role": "user", "content": "hello"})).unwrap();
+        assert_eq!(message.content, Content::Text("hello".into()));
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            json!({"role": "user", "content": "hello"})
+        );
+    }
+
+    #[test]
+    fn parts_round_trip() {
+        let wire = json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "what is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]
+        });
+        let message: Message = serde_json::from_value(wire.clone()).unwrap();
+        assert_eq!(
+            message.content,
+            Content::Parts(vec![
+                ContentPart::Text {
+                    text: "what is this?".into()
+                },
+                ContentPart::ImageUrl {
+                    image_url: ImageUrl {
+                        url: "data:image/png;base64,AAAA".into()
+                    }
+                },
+            ])
+        );
+        assert!(message.has_image());
+        assert_eq!(serde_json::to_value(&message).unwrap(), wire);
+    }
+}
@@ -1,138 +1,435 @@
-use crate::cmd;
-use crate::groq::GroqClient;
+use crate::agent::{StepBudget, StopReason};
+use crate::attach;
+use crate::cli::Approval;
+use crate::cmd::{self, Builtin};
+use crate::command_policy::{self, CommandRisk};
+use crate::edit::{self, ask, ask_key, rejection_reason};
 use crate::groq::Message;
+use crate::plan;
+use crate::screenshot;
 use colored::*;
-use std::{
-    io::{self, Write},
-    path::PathBuf,
-};
+use std::path::PathBuf;
 
-pub async fn handle_reply(
-    reply: &str,
-    history: &mut Vec<Message>,
-    current_dir: &mut PathBuf,
-    has_display: bool,
-    groq_client: &GroqClient, // 👈 ADD THIS
-) -> bool {
-    let mut msg = String::new();
-    let mut cmd = String::new();
+/// The `MSG:` / `CMD:` / `DONE:` parts of a model reply
+pub struct ParsedReply {
+    pub msg: String,
+    pub cmd: String,
+    pub done: Option<String>,
+}
+
+pub fn parse_reply(reply: &str) -> ParsedReply {
+    let mut parsed = ParsedReply {
+        msg: String::new(),
+        cmd: String::new(),
+        done: None,
+    };
 
-    // 1. Parse Response
     for line in reply.lines() {
         if let Some(rest) = line.strip_prefix("MSG:") {
-            if !msg.is_empty() {
-                msg.push('\n');
+            if !parsed.msg.is_empty() {
+                parsed.msg.push('\n');
             }
-            msg.push_str(rest.trim());
+            parsed.msg.push_str(rest.trim());
         } else if let Some(rest) = line.strip_prefix("CMD:") {
-            cmd = rest.trim().to_string();
+            parsed.cmd = rest.trim().to_string();
+        } else if let Some(rest) = line.strip_prefix("DONE:") {
+            parsed.done = Some(rest.trim().to_string());
         }
     }
 
+    parsed
+}
+
+/// What the main loop should do after a reply has been handled
+pub enum ReplyOutcome {
+    /// A command ran and its output is in history; ask the model again
+    Continue,
+    /// The model answered without a command or signalled `DONE:`
+    Finished,
+    /// The user declined the proposed command
+    Cancelled,
+    /// The step budget refused to go on
+    Stopped(StopReason),
+}
+
+pub async fn handle_reply(
+    reply: &str,
+    history: &mut Vec<Message>,
+    current_dir: &mut PathBuf,
+    has_display: bool,
+    budget: &mut StepBudget,
+    approval: Approval,
+) -> ReplyOutcome {
+    // 1. Parse Response
+    let ParsedReply { msg, mut cmd, done } = parse_reply(reply);
+
     if !msg.is_empty() {
         println!("{} {}", "AI:".bold().green(), msg);
     }
 
-    if cmd.is_empty() {
+    // DONE: wins over any command in the same reply
+    if let Some(summary) = done {
+        if !summary.is_empty() {
+            println!("{} {}", "✔ Done:".green().bold(), summary);
+        }
         history.push(Message {
             role: "assistant".into(),
-            content: reply.to_string(),
+            content: reply.into(),
         });
-        return false;
+        return ReplyOutcome::Finished;
     }
 
-    // 2. Check Display Capabilities
-    if !has_display && cmd::is_screenshot_command(&cmd) {
-        println!(
-            "{}",
-            "Screenshots blocked: no graphical display.".red().bold()
+    if let Some(mut plan) = plan::parse_plan(reply) {
+        return handle_plan(
+            &mut plan,
+            reply,
+            history,
+            current_dir,
+            has_display,
+            budget,
+            approval,
         );
+    }
+
+    if cmd.is_empty() {
         history.push(Message {
             role: "assistant".into(),
-            content: "Screenshot blocked: no graphical display.".into(),
+            content: reply.into(),
         });
-        return false;
+        return ReplyOutcome::Finished;
     }
 
-    // 3. Handle 'cd' internally
-    if let Some(path) = cmd.strip_prefix("cd").map(|s| s.trim()) {
-        let target = cmd::resolve_cd_target(path, current_dir);
-        if target.exists() && target.is_dir() {
-            *current_dir = target;
-            println!(
-                "{} {}",
-                "Directory changed to".green(),
-                current_dir.display()
-            );
+    // 2. Handle `cd` and display-less screenshots without a shell
+    if let Some(builtin) = cmd::run_builtin(&cmd, current_dir, has_display) {
+        return finish_builtin(builtin, history);
+    }
+
+    // 3. Refuse loops before bothering the user
+    if let Some(reason) = budget.check_command(&cmd) {
+        history.push(Message {
+            role: "assistant".into(),
+            content: reply.into(),
+        });
+        return ReplyOutcome::Stopped(reason);
+    }
+
+    // 4. Confirm (optionally edit) & Execute
+    println!("{} {}", "Proposed command:".bold().yellow(), cmd.cyan());
+    let original = cmd.clone();
+    let answer = match approval {
+        Approval::Ask => ask("Execute? (y/n, n: <reason>, e = edit, v = $EDITOR): "),
+        Approval::NoExec => {
             history.push(Message {
                 role: "assistant".into(),
-                content: format!("Changed directory to {}", current_dir.display()),
-            });
-        } else {
-            println!("{} {}", "cd failed:".red(), target.display());
-            history.push(Message {
-                role: "user".into(),
-                content: format!("cd failed: {}", target.display()),
+                content: reply.into(),
             });
+            return ReplyOutcome::Finished;
         }
-        return false;
-    }
-
-    // 4. Confirm & Execute
-    println!("{} {}", "Proposed command:".bold().yellow(), cmd.cyan());
-    print!("{}", "Execute? (y/n): ".bold());
-    io::stdout().flush().unwrap();
+        Approval::Yes => match command_policy::classify_command(&cmd) {
+            CommandRisk::Dangerous => {
+                println!(
+                    "{}",
+                    "Refusing to auto-run a dangerous command.".red().bold()
+                );
+                history.push(Message {
+                    role: "assistant".into(),
+                    content: reply.into(),
+                });
+                return ReplyOutcome::Cancelled;
+            }
+            _ => "y".to_string(),
+        },
+    };
+    let choice = answer.to_lowercase();
 
-    let mut confirm = String::new();
-    io::stdin().read_line(&mut confirm).unwrap();
+    if let Some(reason) = rejection_reason(&answer) {
+        println!("{}", "Rejected, asking for an alternative...".dimmed());
+        history.push(Message {
+            role: "assistant".into(),
+            content: reply.into(),
+        });
+        history.push(Message {
+            role: "user".into(),
+            content: format!("COMMAND_REJECTED:\ncommand: {}\nreason: {}", cmd, reason).into(),
+        });
+        return ReplyOutcome::Continue;
+    }
 
-    if !confirm.trim().eq_ignore_ascii_case("y") {
-        println!("{}", "Cancelled.".dimmed());
-        return false;
+    match choice.as_str() {
+        "y" => {}
+        "e" | "v" => {
+            let edited = if choice == "e" {
+                edit::edit_inline(&cmd)
+            } else {
+                edit::edit_external(&cmd)
+            };
+            let Some(edited) = edited else {
+                println!("{}", "Cancelled.".dimmed());
+                return ReplyOutcome::Cancelled;
+            };
+            cmd = edited;
+            // The edit may have turned it into a `cd` or a screenshot
+            if let Some(builtin) = cmd::run_builtin(&cmd, current_dir, has_display) {
+                history.push(Message {
+                    role: "assistant".into(),
+                    content: reply.into(),
+                });
+                push_edited(history, &original, &cmd);
+                return finish_builtin(builtin, history);
+            }
+            if let Some(reason) = budget.check_command(&cmd) {
+                history.push(Message {
+                    role: "assistant".into(),
+                    content: reply.into(),
+                });
+                push_edited(history, &original, &cmd);
+                return ReplyOutcome::Stopped(reason);
+            }
+        }
+        _ => {
+            println!("{}", "Cancelled.".dimmed());
+            return ReplyOutcome::Cancelled;
+        }
     }
 
     let result = cmd::execute_and_capture(&cmd, current_dir);
     println!("{}", result.user_view);
-    if let Some(fix) = &result.suggestion {
-        println!(
-            "{} Did you mean:\n{} {}",
-            "AI:".bold().green(),
-            "Proposed command:".bold().yellow(),
-            fix.cyan()
-        );
+    // The file named on the command line, else whatever image it wrote here
+    let screenshot = if result.exit_code == 0 && screenshot::is_screenshot_command(&cmd) {
+        screenshot::output_path(&cmd, current_dir).or_else(|| {
+            result
+                .changes
+                .find_written(&["png", "jpg", "jpeg"])
+                .map(|path| current_dir.join(path))
+        })
+    } else {
+        None
+    };
+    history.push(Message {
+        role: "assistant".into(),
+        content: reply.into(),
+    });
+    push_edited(history, &original, &cmd);
+
+    // A screenshot goes into the conversation itself so the model can look at it
+    let output = format!("COMMAND_OUTPUT:\n{}", result.ai_view);
+    let output = match screenshot.map(|path| {
+        let image = attach::image_part(&path);
+        screenshot::discard(&path);
+        (image, path)
+    }) {
+        Some((Ok(image), path)) => {
+            println!("{} {}", "Screenshot attached:".green(), path.display());
+            Message::with_image("user", output, image)
+        }
+        Some((Err(e), path)) => {
+            println!(
+                "{} {}: {}",
+                "Screenshot not attached:".red(),
+                path.display(),
+                e
+            );
+            Message {
+                role: "user".into(),
+                content: output.into(),
+            }
+        }
+        None => Message {
+            role: "user".into(),
+            content: output.into(),
+        },
+    };
+    history.push(output);
+
+    if let Some(reason) = budget.record(&cmd, result.exit_code) {
+        print_suggestions(&result.suggestions);
+        return ReplyOutcome::Stopped(reason);
+    }
+
+    // 5. Let the user apply one of the local fixes and report it like any other step
+    if approval != Approval::Ask {
+        print_suggestions(&result.suggestions);
+        return ReplyOutcome::Continue;
+    }
+    let Some(fix) = offer_fix(&result.suggestions) else {
+        return ReplyOutcome::Continue;
+    };
+    if let Some(reason) = budget.check_command(&fix) {
+        return ReplyOutcome::Stopped(reason);
+    }
+
+    let fixed = cmd::execute_and_capture(&fix, current_dir);
+    println!("{}", fixed.user_view);
+    print_suggestions(&fixed.suggestions);
+    history.push(Message {
+        role: "user".into(),
+        content: format!(
+            "COMMAND_OUTPUT:\n(suggested fix, run by the user)\n{}",
+            fixed.ai_view
+        )
+        .into(),
+    });
+    if let Some(reason) = budget.record(&fix, fixed.exit_code) {
+        return ReplyOutcome::Stopped(reason);
     }
-    if let Some(image_path) = &result.created_file {
-        println!("{} Analyzing captured screenshot...", "AI:".bold().green());
 
-        match groq_client
-            .analyze_image_file(
-                image_path.to_str().unwrap(),
-                "Analyze this screenshot and explain what it shows.",
+    ReplyOutcome::Continue
+}
+
+/// Report a command `run_builtin` took care of
+/// Let the model see the user's correction so it proposes the right thing next time
+fn push_edited(history: &mut Vec<Message>, original: &str, edited: &str) {
+    if edited != original {
+        history.push(Message {
+            role: "user".into(),
+            content: format!(
+                "COMMAND_EDITED:\noriginal: {}\nedited: {}",
+                original, edited
             )
-            .await
-        {
-            Ok(analysis) => {
-                println!("{} {}", "Image analysis:".bold().cyan(), analysis);
+            .into(),
+        });
+    }
+}
 
-                // Feed analysis back into history
-                history.push(Message {
-                    role: "assistant".into(),
-                    content: format!("IMAGE_ANALYSIS:\n{}", analysis),
-                });
-            }
-            Err(err) => {
-                println!("{} {}", "Image analysis failed:".red(), err);
-            }
+fn finish_builtin(builtin: Builtin, history: &mut Vec<Message>) -> ReplyOutcome {
+    match builtin {
+        Builtin::NoDisplay => {
+            println!(
+                "{}",
+                "Screenshots blocked: no graphical display.".red().bold()
+            );
+            history.push(Message {
+                role: "assistant".into(),
+                content: "Screenshot blocked: no graphical display.".into(),
+            });
+        }
+        Builtin::ChangedDir(dir) => {
+            println!("{} {}", "Directory changed to".green(), dir.display());
+            history.push(Message {
+                role: "assistant".into(),
+                content: format!("Changed directory to {}", dir.display()).into(),
+            });
+        }
+        Builtin::CdFailed(target) => {
+            println!("{} {}", "cd failed:".red(), target.display());
+            history.push(Message {
+                role: "user".into(),
+                content: format!("cd failed: {}", target.display()).into(),
+            });
         }
     }
+    ReplyOutcome::Finished
+}
+
+fn handle_plan(
+    plan: &mut plan::Plan,
+    reply: &str,
+    history: &mut Vec<Message>,
+    current_dir: &mut PathBuf,
+    has_display: bool,
+    budget: &mut StepBudget,
+    approval: Approval,
+) -> ReplyOutcome {
+    plan::print_checklist(plan);
+    if approval == Approval::NoExec {
+        history.push(Message {
+            role: "assistant".into(),
+            content: reply.into(),
+        });
+        return ReplyOutcome::Finished;
+    }
+
+    let report = plan::run_plan(plan, current_dir, has_display, budget, approval);
+    if report.ran_any {
+        plan::print_checklist(plan);
+    }
+
     history.push(Message {
         role: "assistant".into(),
-        content: reply.to_string(),
+        content: reply.into(),
     });
+    for (original, edited) in &report.edits {
+        push_edited(history, original, edited);
+    }
+
+    if let Some(reason) = report.rejected {
+        println!("{}", "Rejected, asking for a new plan...".dimmed());
+        history.push(Message {
+            role: "user".into(),
+            content: format!("PLAN_REJECTED:\nreason: {}", reason).into(),
+        });
+        return ReplyOutcome::Continue;
+    }
+
+    if !report.ran_any && report.stop.is_none() {
+        return ReplyOutcome::Cancelled;
+    }
+
     history.push(Message {
         role: "user".into(),
-        content: format!("COMMAND_OUTPUT:\n{}", result.ai_view),
+        content: format!("PLAN_RESULT:\n{}", report.ai_view).into(),
     });
 
-    true
+    // The user said quit: keep the results for later, but don't ask the model again
+    if report.quit {
+        println!("{}", "Stopped.".dimmed());
+        return ReplyOutcome::Cancelled;
+    }
+
+    match report.stop {
+        Some(reason) => ReplyOutcome::Stopped(reason),
+        None => ReplyOutcome::Continue,
+    }
+}
+
+/// List typo fixes from `suggest_fix`, best first
+pub fn print_suggestions(suggestions: &[String]) {
+    match suggestions {
+        [] => {}
+        [only] => println!("{} {}", "Did you mean:".bold().yellow(), only.cyan()),
+        _ => {
+            println!("{}", "Did you mean:".bold().yellow());
+            for (i, fix) in suggestions.iter().enumerate() {
+                println!("  {}. {}", i + 1, fix.cyan());
+            }
+        }
+    }
+}
+
+/// Offer the fixes from `suggest_fix` behind a single key press: run the best one
+/// (or another by number), edit it first, or ignore them. The returned command has
+/// been through the policy check; a dangerous fix needs an explicit `yes`.
+pub fn offer_fix(suggestions: &[String]) -> Option<String> {
+    let best = suggestions.first()?;
+    print_suggestions(suggestions);
+
+    let prompt = if suggestions.len() == 1 {
+        "Fix: [r]un, [e]dit, [i]gnore? ".to_string()
+    } else {
+        format!(
+            "Fix: [r]un 1, [1-{}] run, [e]dit 1, [i]gnore? ",
+            suggestions.len()
+        )
+    };
+
+    let fix = match ask_key(&prompt) {
+        'r' | 'y' => best.clone(),
+        'e' => edit::edit_inline(best)?,
+        c @ '1'..='9' => suggestions.get(c as usize - '1' as usize)?.clone(),
+        _ => return None,
+    };
+
+    match command_policy::classify_command(&fix) {
+        CommandRisk::Safe => Some(fix),
+        CommandRisk::Caution => {
+            println!("{} {}", CommandRisk::Caution, fix.cyan());
+            Some(fix)
+        }
+        CommandRisk::Dangerous => {
+            println!("{} {}", CommandRisk::Dangerous, fix.cyan());
+            ask("Type 'yes' to run it anyway: ")
+                .eq_ignore_ascii_case("yes")
+                .then_some(fix)
+        }
+    }
 }
@@ -1,24 +1,161 @@
+use clap::Parser;
 use colored::*;
 use std::{
     env,
-    io::{self, Write},
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::ExitCode,
 };
 
+mod agent;
+mod attach;
+mod cli;
 mod cmd;
+mod cmdline;
+mod command_policy;
+mod config;
+mod edit;
+mod explain;
+mod flags;
 mod groq;
 mod handler;
+mod meta;
+mod notfound;
+mod plan;
+mod repl;
+mod screenshot;
+mod shell;
 mod sys;
+mod tts;
+mod workspace;
 
-use groq::{AudioRecorder, GroqClient, Message};
+use agent::{RequestOutcome, StepBudget};
+use cli::{Approval, Cli, Command, exit};
+use groq::{GroqClient, Message};
+use meta::{MetaContext, MetaOutcome};
+use repl::Repl;
+use shell::Why;
+use tts::Speaker;
+
+const DEFAULT_MODEL: &str = "openai/gpt-oss-120b";
+/// What `cat log | ai-terminal` asks when no request is given
+const PIPED_ONLY_REQUEST: &str = "Explain this input and point out any errors in it.";
 
 #[tokio::main]
-async fn main() {
-    dotenvy::dotenv().expect(".env file not found");
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    if let Err(e) = config::load(cli.config.as_deref()) {
+        eprintln!("{} {}", "Config error:".red(), e);
+        return ExitCode::from(exit::USAGE);
+    }
+
+    if let Some(Command::ShellInit { shell }) = cli.command {
+        print!("{}", shell::init_script(shell));
+        return ExitCode::from(exit::SUCCESS);
+    }
+
+    let mut request = cli.request();
+    let mut start_dir = cli.cwd.clone();
+    if let Some(Command::Why) = cli.command {
+        match shell::why(cli.approval()) {
+            Why::Ask { prompt, cwd } => {
+                request = Some(prompt);
+                start_dir = Some(cwd);
+            }
+            Why::Done(code) => return ExitCode::from(code),
+        }
+    }
 
-    let mut current_dir = env::current_dir().expect("Failed to get cwd");
+    let Ok(api_key) = env::var("GROQ_API_KEY") else {
+        eprintln!(
+            "{} GROQ_API_KEY is not set (use the environment, .env or --config)",
+            "Error:".red()
+        );
+        return ExitCode::from(exit::USAGE);
+    };
+
+    let mut current_dir = match &start_dir {
+        Some(dir) => match dir.canonicalize() {
+            Ok(d) if d.is_dir() => d,
+            _ => {
+                eprintln!("{} not a directory: {}", "Error:".red(), dir.display());
+                return ExitCode::from(exit::USAGE);
+            }
+        },
+        None => env::current_dir().expect("Failed to get cwd"),
+    };
     let (has_display, wayland, x11) = sys::detect_display();
     let system_info = sys::gather_info(&current_dir, has_display, wayland, x11);
 
+    let model = cli
+        .model
+        .clone()
+        .or_else(|| env::var("AI_MODEL").ok())
+        .unwrap_or_else(|| DEFAULT_MODEL.into());
+    let mut groq_client = GroqClient::new(api_key, model);
+    if let Ok(vision_model) = env::var("AI_VISION_MODEL") {
+        groq_client.set_vision_model(vision_model);
+    }
+
+    if let Some(Command::Suggest { line }) = &cli.command {
+        let system = agent::system_message(&system_info);
+        return ExitCode::from(shell::suggest(&groq_client, system, &line.join(" ")).await);
+    }
+
+    if let Some(Command::Explain { command }) = &cli.command {
+        explain::explain(&groq_client, &command.join(" ")).await;
+        return ExitCode::from(exit::SUCCESS);
+    }
+
+    let mut history: Vec<Message> = vec![agent::system_message(&system_info)];
+    let mut budget = StepBudget::from_env();
+
+    // Piped input is one-shot even without a request: the REPL can't share stdin
+    let (request, piped) = match (request, sys::read_piped_input()) {
+        (request, Some(piped)) => (
+            Some(request.unwrap_or_else(|| PIPED_ONLY_REQUEST.into())),
+            Some(piped),
+        ),
+        (None, None) if !io::stdin().is_terminal() => {
+            eprintln!(
+                "{} stdin is not a terminal and nothing was piped; pass a request instead",
+                "Error:".red()
+            );
+            return ExitCode::from(exit::USAGE);
+        }
+        (request, None) => (request, None),
+    };
+
+    if let Some(request) = request {
+        // `@` mentions come from the user's own words only, never from the pipe
+        let mut message = attach::message(&request, &current_dir);
+        if let Some(piped) = piped {
+            message
+                .content
+                .prepend(&format!("PIPED_INPUT:\n```\n{}\n```\n\n", piped));
+        }
+        history.push(message);
+        let outcome = agent::run_request(
+            &mut history,
+            &mut current_dir,
+            has_display,
+            &groq_client,
+            &mut budget,
+            cli.approval(),
+            None,
+        )
+        .await;
+
+        return ExitCode::from(match outcome {
+            RequestOutcome::Finished if budget.last_failed() => exit::COMMAND_FAILED,
+            RequestOutcome::Finished => exit::SUCCESS,
+            RequestOutcome::Cancelled => exit::DECLINED,
+            RequestOutcome::Stopped => exit::COMMAND_FAILED,
+            RequestOutcome::Failed => exit::API_ERROR,
+        });
+    }
+
     println!("{}", "Welcome to your AI Terminal!".bold().green());
     println!(
         "{} {}\n",
@@ -26,39 +163,43 @@ async fn main() {
         current_dir.display().to_string().cyan()
     );
 
-    let groq_client = GroqClient::new(
-        env::var("GROQ_API_KEY").expect("GROQ_API_KEY not set"),
-        "openai/gpt-oss-120b",
-    );
+    run_repl(
+        &mut history,
+        &mut current_dir,
+        has_display,
+        &mut groq_client,
+        &mut budget,
+        cli.approval(),
+    )
+    .await;
+
+    ExitCode::from(exit::SUCCESS)
+}
 
-    let mut history: Vec<Message> = vec![Message {
-        role: "system".into(),
-        content: format!(
-            r#"
-            You are an AI-powered terminal assistant.
-            
-            SYSTEM INFORMATION:
-            {}
-
-            PROTOCOL:
-            MSG: <text>
-            CMD: <linux command>
-            
-            RULES:
-            - After CMD execution, you will receive COMMAND_OUTPUT.
-            - You MUST verify the output before claiming success.
-            "#,
-            system_info
-        ),
-    }];
+async fn run_repl(
+    history: &mut Vec<Message>,
+    current_dir: &mut PathBuf,
+    has_display: bool,
+    groq_client: &mut GroqClient,
+    budget: &mut StepBudget,
+    approval: Approval,
+) {
+    let mut repl = Repl::new().expect("Failed to initialise line editor");
+    let mut audio_device = env::var("AI_AUDIO_DEVICE").ok().filter(|d| !d.is_empty());
+    let mut speaker = Speaker::from_env();
 
     // --- MAIN LOOP ---
     loop {
-        print!("{} ", format!("{} >", current_dir.display()).cyan().bold());
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        for (label, reason) in speaker.take_failures() {
+            println!("{} {}", format!("{}:", label).yellow(), reason);
+        }
+        let prompt = format!("{} ", format!("{} >", current_dir.display()).cyan().bold());
+        let Some(input) = repl.read_line(&prompt, current_dir) else {
+            println!("{}", "Goodbye!".bold().yellow());
+            break;
+        };
+        // Whatever the user submits, they are done listening
+        speaker.stop();
         let input = input.trim();
 
         if input.eq_ignore_ascii_case("exit") || input.eq_ignore_ascii_case("quit") {
@@ -72,125 +213,53 @@ async fn main() {
 
         // Variable to hold either the typed text OR the transcribed voice text
         let mut final_prompt = input.to_string();
+        // A meta-command may hand over a ready message instead (e.g. with a screenshot)
+        let mut message = None;
 
-        // 🎤 VOICE MODE TRIGGER
-        // If the user typed ":rec" or ":voice", we start recording
-        if input == ":rec" || input == ":voice" {
-            println!(
-                "{}",
-                "🎙️  Recording... Press ENTER to stop.".red().bold().blink()
-            );
-
-            // 1. Start Audio Driver
-            let recorder = match AudioRecorder::start() {
-                Ok(r) => r,
+        if let Some(parsed) = meta::parse(input) {
+            let (command, args) = match parsed {
+                Ok(p) => p,
                 Err(e) => {
-                    println!("{} {}", "Failed to init mic:".red(), e);
+                    println!("{}", e.red());
                     continue;
                 }
             };
-
-            // 2. Block until user hits Enter
-            let mut pause = String::new();
-            io::stdin().read_line(&mut pause).unwrap();
-
-            // 3. Stop and Save
-            let temp_file = "voice_temp.wav";
-            println!("{}", "Processing audio...".dimmed());
-
-            if let Err(e) = recorder.stop_and_save(temp_file) {
-                println!("{} {}", "Audio save error:".red(), e);
-                continue;
-            }
-
-            // 4. Send to Cloud (Groq Whisper)
-            // Note: This blocks the UI briefly. For a smoother experience, you could wrap this in a spinner too.
-            match groq_client.transcribe_audio(temp_file).await {
-                Ok(text) => {
-                    println!("{} {}", "Transcribed:".green().bold(), text.italic());
-                    final_prompt = text; // Replace ":rec" with the actual spoken words
-
-                    // Cleanup the temporary wav file
-                    let _ = std::fs::remove_file(temp_file);
-                }
-                Err(e) => {
-                    println!("{} {}", "Transcription failed:".red(), e);
-                    continue;
+            let ctx = MetaContext {
+                history,
+                current_dir,
+                groq_client,
+                audio_device: &mut audio_device,
+                speaker: &mut speaker,
+            };
+            match meta::execute(command, &args, ctx).await {
+                MetaOutcome::Handled => continue,
+                MetaOutcome::Prompt(text) => final_prompt = text,
+                MetaOutcome::Send(m) => message = Some(m),
+                MetaOutcome::Quit => {
+                    println!("{}", "Goodbye!".bold().yellow());
+                    break;
                 }
             }
         }
 
         // If transcription returned empty string or user just hit enter, skip processing
-        if final_prompt.is_empty() {
+        if message.is_none() && final_prompt.is_empty() {
             continue;
         }
 
-        // Push the final prompt (typed or spoken) to history
-        history.push(Message {
-            role: "user".into(),
-            content: final_prompt.clone(),
-        });
-
-        // --- AI PROCESSING LOOP ---
-        loop {
-            // 1. Create spinner channel
-            let (tx, mut rx) = tokio::sync::oneshot::channel();
-
-            // 2. Spawn the spinner in a separate background task
-            let spinner_handle = tokio::spawn(async move {
-                let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-                let mut i = 0;
-                loop {
-                    // Check if we received the stop signal
-                    if rx.try_recv().is_ok() {
-                        break;
-                    }
-
-                    print!("\r{} AI thinking...", frames[i % frames.len()].cyan());
-                    io::stdout().flush().unwrap();
-
-                    tokio::time::sleep(std::time::Duration::from_millis(80)).await;
-                    i += 1;
-                }
-                // Clear the spinner line when done
-                print!("\r\x1b[K");
-                io::stdout().flush().unwrap();
-            });
-
-            // 3. Call AI
-            let reply_result = groq_client.chat(history.clone()).await;
-
-            // 4. Stop spinner
-            let _ = tx.send(());
-            spinner_handle.await.unwrap(); // Wait for spinner to clean up
-
-            // 5. Handle the result
-            let reply = match reply_result {
-                Ok(r) => r,
-                Err(err) => {
-                    println!("{} {}", "Error:".red(), err);
-                    break;
-                }
-            };
-
-            // Clear line one last time to be safe
-            print!("\r\x1b[K");
-
-            let should_continue = handler::handle_reply(
-                &reply,
-                &mut history,
-                &mut current_dir,
-                has_display,
-                &groq_client, // Note: Ensure your handler signature accepts groq_client if needed for recursion
-                              // If your handler doesn't take groq_client, remove this argument.
-                              // Based on previous context, you might not be passing groq_client to handler yet,
-                              // but checking your provided file, it looks like you updated handler signature.
-            )
-            .await;
-
-            if !should_continue {
-                break;
-            }
-        }
+        // Push the final prompt (typed or spoken) to history, with any @files
+        let message = message.unwrap_or_else(|| attach::message(&final_prompt, current_dir));
+        history.push(message);
+
+        agent::run_request(
+            history,
+            current_dir,
+            has_display,
+            groq_client,
+            budget,
+            approval,
+            Some(&speaker),
+        )
+        .await;
     }
 }
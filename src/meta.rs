@@ -0,0 +1,685 @@
+use crate::attach;
+use crate::cmd;
+use crate::command_policy;
+use crate::config;
+use crate::explain;
+use crate::groq::{self, AudioRecorder, GroqClient, Message, TARGET_RATE, VadConfig, VadState};
+use crate::handler;
+use crate::screenshot;
+use crate::tts::Speaker;
+use colored::*;
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+/// How long `:audio test` records
+const AUDIO_TEST_SECS: u64 = 3;
+
+/// How many arguments a meta-command accepts. Arguments are the raw rest of
+/// the line, so paths and commands containing spaces need no quoting.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Args {
+    None,
+    Optional,
+    Required,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Help,
+    Quit,
+    Clear,
+    History,
+    Cd,
+    Model,
+    Policy,
+    Explain,
+    Cost,
+    Run,
+    Screenshot,
+    Voice,
+    Audio,
+    Speak,
+}
+
+pub struct MetaCommand {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub args: Args,
+    pub action: Action,
+}
+
+/// Every `:` command known to the REPL. Add an entry here and an arm in `execute`.
+pub const COMMANDS: &[MetaCommand] = &[
+    MetaCommand {
+        name: ":help",
+        aliases: &[":h", ":?"],
+        usage: ":help [command]",
+        help: "List meta-commands, or show help for one",
+        args: Args::Optional,
+        action: Action::Help,
+    },
+    MetaCommand {
+        name: ":quit",
+        aliases: &[":exit", ":q"],
+        usage: ":quit",
+        help: "Leave the terminal",
+        args: Args::None,
+        action: Action::Quit,
+    },
+    MetaCommand {
+        name: ":clear",
+        aliases: &[],
+        usage: ":clear",
+        help: "Forget the conversation (keeps the system prompt)",
+        args: Args::None,
+        action: Action::Clear,
+    },
+    MetaCommand {
+        name: ":history",
+        aliases: &[],
+        usage: ":history",
+        help: "Show the conversation sent to the model",
+        args: Args::None,
+        action: Action::History,
+    },
+    MetaCommand {
+        name: ":cd",
+        aliases: &[],
+        usage: ":cd [dir]",
+        help: "Change the working directory (home if omitted)",
+        args: Args::Optional,
+        action: Action::Cd,
+    },
+    MetaCommand {
+        name: ":model",
+        aliases: &[],
+        usage: ":model [name]",
+        help: "Show or switch the chat model",
+        args: Args::Optional,
+        action: Action::Model,
+    },
+    MetaCommand {
+        name: ":policy",
+        aliases: &[],
+        usage: ":policy <command>",
+        help: "Show the risk verdict for a command without running it",
+        args: Args::Required,
+        action: Action::Policy,
+    },
+    MetaCommand {
+        name: ":explain",
+        aliases: &[],
+        usage: ":explain <command>",
+        help: "Break a command into its parts and explain each, without running it",
+        args: Args::Required,
+        action: Action::Explain,
+    },
+    MetaCommand {
+        name: ":cost",
+        aliases: &[],
+        usage: ":cost",
+        help: "Show token usage for this session",
+        args: Args::None,
+        action: Action::Cost,
+    },
+    MetaCommand {
+        name: ":run",
+        aliases: &["!"],
+        usage: ":run <command>",
+        help: "Run a command directly, without asking the model",
+        args: Args::Required,
+        action: Action::Run,
+    },
+    MetaCommand {
+        name: ":screenshot",
+        aliases: &[":shot"],
+        usage: ":screenshot [question]",
+        help: "Capture the screen and ask the AI about it (describes it by default)",
+        args: Args::Optional,
+        action: Action::Screenshot,
+    },
+    MetaCommand {
+        name: ":rec",
+        aliases: &[":voice"],
+        usage: ":rec [manual]",
+        help: "Record a spoken prompt; stops when you go quiet (manual: press ENTER to stop)",
+        args: Args::Optional,
+        action: Action::Voice,
+    },
+    MetaCommand {
+        name: ":audio",
+        aliases: &[],
+        usage: ":audio [devices | use <name> | use default | test]",
+        help: "List input devices, choose the microphone, or test its level",
+        args: Args::Optional,
+        action: Action::Audio,
+    },
+    MetaCommand {
+        name: ":speak",
+        aliases: &[],
+        usage: ":speak [on | off | stop]",
+        help: "Read the AI's messages aloud, or interrupt what it is saying",
+        args: Args::Optional,
+        action: Action::Speak,
+    },
+];
+
+/// State a meta-command may read or change
+pub struct MetaContext<'a> {
+    pub history: &'a mut Vec<Message>,
+    pub current_dir: &'a mut PathBuf,
+    pub groq_client: &'a mut GroqClient,
+    /// Input device for `:rec`, None for the system default
+    pub audio_device: &'a mut Option<String>,
+    pub speaker: &'a mut Speaker,
+}
+
+pub enum MetaOutcome {
+    /// Nothing more to do; show the prompt again
+    Handled,
+    /// Send this text to the model as if the user had typed it
+    Prompt(String),
+    /// Send this message to the model (used when it carries an image)
+    Send(Message),
+    Quit,
+}
+
+/// Every name and alias, for tab completion
+pub fn names() -> impl Iterator<Item = &'static str> {
+    COMMANDS
+        .iter()
+        .flat_map(|c| std::iter::once(c.name).chain(c.aliases.iter().copied()))
+}
+
+fn find(name: &str) -> Option<&'static MetaCommand> {
+    COMMANDS
+        .iter()
+        .find(|c| c.name == name || c.aliases.contains(&name))
+}
+
+/// Split `input` into a known command and its argument string.
+/// Returns None if the input is not a meta-command at all.
+pub fn parse(input: &str) -> Option<Result<(&'static MetaCommand, String), String>> {
+    let input = input.trim();
+    if !input.starts_with(':') && !input.starts_with('!') {
+        return None;
+    }
+
+    // `!ls` is shorthand for `:run ls`
+    let (name, rest) = if let Some(rest) = input.strip_prefix('!') {
+        ("!", rest)
+    } else {
+        input.split_once(char::is_whitespace).unwrap_or((input, ""))
+    };
+    let rest = rest.trim().to_string();
+
+    let Some(command) = find(name) else {
+        return Some(Err(format!("Unknown command {} (try :help)", name)));
+    };
+
+    match command.args {
+        Args::None if !rest.is_empty() => Some(Err(format!("{} takes no arguments", command.name))),
+        Args::Required if rest.is_empty() => Some(Err(format!("Usage: {}", command.usage))),
+        _ => Some(Ok((command, rest))),
+    }
+}
+
+pub async fn execute(command: &MetaCommand, args: &str, ctx: MetaContext<'_>) -> MetaOutcome {
+    match command.action {
+        Action::Help => print_help(args),
+        Action::Quit => return MetaOutcome::Quit,
+        Action::Clear => {
+            ctx.history.truncate(1);
+            println!("{}", "Conversation cleared.".dimmed());
+        }
+        Action::History => {
+            for msg in ctx.history.iter().skip(1) {
+                let role = match msg.role.as_str() {
+                    "user" => msg.role.cyan(),
+                    _ => msg.role.green(),
+                };
+                println!(
+                    "{} {}",
+                    format!("[{}]", role).bold(),
+                    preview(&msg.content.text())
+                );
+            }
+        }
+        Action::Cd => {
+            let target = cmd::resolve_cd_target(args, ctx.current_dir);
+            if target.is_dir() {
+                *ctx.current_dir = target;
+                println!(
+                    "{} {}",
+                    "Directory changed to".green(),
+                    ctx.current_dir.display()
+                );
+                ctx.history.push(Message {
+                    role: "user".into(),
+                    content: format!("I changed directory to {}", ctx.current_dir.display()).into(),
+                });
+            } else {
+                println!("{} {}", "cd failed:".red(), target.display());
+            }
+        }
+        Action::Model => {
+            if args.is_empty() {
+                println!("{} {}", "Model:".bold(), ctx.groq_client.model().cyan());
+                println!(
+                    "{} {}",
+                    "Vision model:".bold(),
+                    ctx.groq_client.vision_model().cyan()
+                );
+            } else {
+                ctx.groq_client.set_model(args);
+                println!("{} {}", "Model set to".green(), args.cyan());
+            }
+        }
+        Action::Policy => {
+            let risk = command_policy::classify_command(args);
+            println!("{} {}", "Risk:".bold(), risk);
+        }
+        Action::Explain => explain::explain(ctx.groq_client, args).await,
+        Action::Cost => print_cost(ctx.groq_client),
+        Action::Run => {
+            let result = cmd::execute_and_capture(args, ctx.current_dir);
+            println!("{}", result.user_view);
+            ctx.history.push(Message {
+                role: "user".into(),
+                content: format!("USER_RAN_COMMAND:\n{}", result.ai_view).into(),
+            });
+            if let Some(fix) = handler::offer_fix(&result.suggestions) {
+                let fixed = cmd::execute_and_capture(&fix, ctx.current_dir);
+                println!("{}", fixed.user_view);
+                handler::print_suggestions(&fixed.suggestions);
+                ctx.history.push(Message {
+                    role: "user".into(),
+                    content: format!("USER_RAN_COMMAND:\n{}", fixed.ai_view).into(),
+                });
+            }
+        }
+        Action::Screenshot => {
+            let path = match screenshot::capture() {
+                Ok(path) => path,
+                Err(e) => {
+                    println!("{} {}", "Screenshot failed:".red(), e);
+                    return MetaOutcome::Handled;
+                }
+            };
+
+            let question = if args.is_empty() {
+                "Describe what this screenshot shows."
+            } else {
+                args
+            };
+            let image = attach::image_part(&path);
+            screenshot::discard(&path);
+            match image {
+                Ok(image) => {
+                    println!("{}", "Screenshot attached.".green());
+                    return MetaOutcome::Send(Message::with_image(
+                        "user",
+                        format!("SCREENSHOT:\n{}", question),
+                        image,
+                    ));
+                }
+                Err(e) => println!("{} {}", "Screenshot failed:".red(), e),
+            }
+        }
+        Action::Voice => {
+            let manual = match args.trim() {
+                "" => false,
+                "manual" => true,
+                other => {
+                    println!("{} {}", "Unknown :rec option:".red(), other);
+                    return MetaOutcome::Handled;
+                }
+            };
+            let device = ctx.audio_device.as_deref();
+            if let Some(text) = record_voice(ctx.groq_client, manual, device).await {
+                return MetaOutcome::Prompt(text);
+            }
+        }
+        Action::Audio => audio(args, ctx.audio_device).await,
+        Action::Speak => speak(args, ctx.speaker),
+    }
+
+    MetaOutcome::Handled
+}
+
+fn print_help(args: &str) {
+    if !args.is_empty() {
+        let name = if args.starts_with(':') {
+            args.to_string()
+        } else {
+            format!(":{}", args)
+        };
+        match find(&name) {
+            Some(c) => {
+                println!("{}  {}", c.usage.cyan(), c.help);
+                if !c.aliases.is_empty() {
+                    println!("{} {}", "aliases:".dimmed(), c.aliases.join(", "));
+                }
+            }
+            None => println!("{} {}", "Unknown command".red(), name),
+        }
+        return;
+    }
+
+    println!("{}", "Meta-commands:".bold());
+    for c in COMMANDS {
+        println!("  {:<20} {}", c.usage.cyan(), c.help);
+    }
+    println!("  {:<20} Leave the terminal", "exit / quit".cyan());
+}
+
+fn print_cost(groq_client: &GroqClient) {
+    let usage = groq_client.usage();
+    println!(
+        "{} {} requests, {} prompt + {} completion tokens",
+        "Usage:".bold(),
+        usage.requests,
+        usage.prompt_tokens,
+        usage.completion_tokens
+    );
+
+    // Prices are per million tokens and depend on the model, so they come from the env
+    let price = |key: &str| env::var(key).ok().and_then(|v| v.parse::<f64>().ok());
+    if let (Some(input), Some(output)) = (
+        price("AI_PRICE_INPUT_PER_M"),
+        price("AI_PRICE_OUTPUT_PER_M"),
+    ) {
+        let cost = usage.prompt_tokens as f64 * input / 1e6
+            + usage.completion_tokens as f64 * output / 1e6;
+        println!("{} ${:.4}", "Estimated cost:".bold(), cost);
+    }
+}
+
+fn preview(content: &str) -> String {
+    let first = content.trim().lines().next().unwrap_or("");
+    let lines = content.trim().lines().count();
+    let mut out: String = first.chars().take(100).collect();
+    if first.chars().count() > 100 || lines > 1 {
+        out.push_str(&format!(" … ({} lines)", lines).dimmed().to_string());
+    }
+    out
+}
+
+async fn audio(args: &str, device: &mut Option<String>) {
+    let (sub, rest) = args.split_once(' ').unwrap_or((args, ""));
+    let rest = rest.trim();
+    match sub {
+        "" => {
+            let current = device.as_deref().unwrap_or("system default");
+            println!("{} {}", "Input device:".bold(), current.cyan());
+            println!(
+                "{}",
+                "Usage: :audio [devices | use <name> | use default | test]".dimmed()
+            );
+        }
+        "devices" => print_devices(device.as_deref()),
+        "use" if rest.is_empty() => println!("{}", "Usage: :audio use <name>".red()),
+        "use" => {
+            let chosen = if rest == "default" {
+                None
+            } else {
+                match groq::resolve_input_device(rest) {
+                    Ok(id) => Some(id),
+                    Err(e) => {
+                        println!("{} {}", "Error:".red(), e);
+                        return;
+                    }
+                }
+            };
+            match config::set("AI_AUDIO_DEVICE", chosen.as_deref()) {
+                Ok(path) => println!(
+                    "{} {} {}",
+                    "Input device set to".green(),
+                    chosen.as_deref().unwrap_or("system default").cyan(),
+                    format!("(saved in {})", path.display()).dimmed()
+                ),
+                Err(e) => println!("{} {}", "Not saved:".yellow(), e),
+            }
+            if config::overridden("AI_AUDIO_DEVICE") {
+                println!(
+                    "{}",
+                    "Note: AI_AUDIO_DEVICE is also set in the environment or .env, which wins over the saved choice on the next start."
+                        .yellow()
+                );
+            }
+            *device = chosen;
+        }
+        "test" => {
+            // Records for a few seconds; keep the sleep off the async workers
+            let device = device.clone();
+            let _ = tokio::task::spawn_blocking(move || test_audio(device.as_deref())).await;
+        }
+        other => println!("{} {}", "Unknown :audio command:".red(), other),
+    }
+}
+
+fn speak(args: &str, speaker: &mut Speaker) {
+    match args.trim() {
+        "" => {
+            let state = if speaker.enabled { "on" } else { "off" };
+            println!("{} {}", "Speech:".bold(), state.cyan());
+            let providers = speaker.provider_names();
+            if providers.is_empty() {
+                println!(
+                    "{}",
+                    "No speech provider (set GROQ_API_KEY or install espeak-ng)".yellow()
+                );
+            } else {
+                println!("{} {}", "Providers:".bold(), providers.join(", ").dimmed());
+            }
+        }
+        "on" => {
+            speaker.enabled = true;
+            println!(
+                "{}",
+                "Speech on: the AI's messages will be read aloud.".green()
+            );
+        }
+        "off" => {
+            speaker.stop();
+            speaker.enabled = false;
+            println!("{}", "Speech off.".green());
+        }
+        "stop" => speaker.stop(),
+        other => println!("{} {}", "Unknown :speak option:".red(), other),
+    }
+}
+
+fn print_devices(selected: Option<&str>) {
+    let devices = groq::input_devices();
+    if devices.is_empty() {
+        println!("{}", "No input devices found.".yellow());
+        return;
+    }
+    for d in devices {
+        let mut marks = Vec::new();
+        if d.is_default {
+            marks.push("default");
+        }
+        if selected == Some(d.id.as_str()) {
+            marks.push("selected");
+        }
+        let marks = if marks.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", marks.join(", "))
+        };
+        println!(
+            "{} {}{} {}",
+            format!("[{}]", d.host).dimmed(),
+            d.id.cyan().bold(),
+            marks.green(),
+            d.name
+        );
+        for config in &d.configs {
+            println!("    {}", config.dimmed());
+        }
+    }
+}
+
+/// Record a few seconds and report how loud the input is
+fn test_audio(device: Option<&str>) {
+    let recorder = match AudioRecorder::start(device) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("{} {}", "Failed to init mic:".red(), e);
+            return;
+        }
+    };
+    println!(
+        "{} {}",
+        format!("Recording {} seconds, speak normally...", AUDIO_TEST_SECS).bold(),
+        format!("{} ({})", recorder.device_name(), recorder.input_format()).dimmed()
+    );
+    thread::sleep(Duration::from_secs(AUDIO_TEST_SECS));
+    let levels = recorder.levels();
+    recorder.stop();
+
+    let db = |v: f32| 20.0 * v.max(1e-6).log10();
+    println!(
+        "{} {:.1} dBFS   {} {:.1} dBFS   {} {}   {} {:.1} s",
+        "Peak:".bold(),
+        db(levels.peak),
+        "RMS:".bold(),
+        db(levels.rms),
+        "Clipped:".bold(),
+        levels.clipped,
+        "Captured:".bold(),
+        levels.duration.as_secs_f32()
+    );
+
+    let threshold = VadConfig::from_env().threshold;
+    if levels.duration.is_zero() || levels.peak == 0.0 {
+        println!(
+            "{}",
+            "The device delivered only silence; is it muted or the wrong one?".red()
+        );
+    } else if levels.clipped > 0 {
+        println!("{}", "Clipping: lower the input gain.".yellow());
+    } else if levels.peak < threshold {
+        println!(
+            "{}",
+            format!(
+                "Too quiet for voice detection (threshold {:.1} dBFS): raise the gain or set AI_VAD_THRESHOLD.",
+                db(threshold)
+            )
+            .yellow()
+        );
+    } else {
+        println!("{}", "Level looks good.".green());
+    }
+}
+
+/// One line showing the input level in dBFS, -60 to 0, and what the VAD thinks
+fn level_meter(level: f32, state: VadState) -> String {
+    const WIDTH: usize = 30;
+    let db = 20.0 * level.max(1e-6).log10();
+    let filled = (((db + 60.0) / 60.0).clamp(0.0, 1.0) * WIDTH as f32).round() as usize;
+    let bar = format!("{}{}", "█".repeat(filled), " ".repeat(WIDTH - filled));
+    let (bar, label) = match state {
+        VadState::Waiting => (bar.dimmed(), "waiting "),
+        VadState::Speaking => (bar.green(), "speaking"),
+        VadState::Pausing => (bar.yellow(), "pause   "),
+    };
+    format!("[{}] {:>4.0} dB {}", bar, db, label.dimmed())
+}
+
+async fn record_voice(
+    groq_client: &GroqClient,
+    manual: bool,
+    device: Option<&str>,
+) -> Option<String> {
+    // 1. Start Audio Driver
+    let recorder = match AudioRecorder::start(device) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("{} {}", "Failed to init mic:".red(), e);
+            return None;
+        }
+    };
+
+    let banner = if manual {
+        "🎙️  Recording... Press ENTER to stop."
+    } else {
+        "🎙️  Listening... Stops when you go quiet."
+    };
+    println!("{}", banner.red().bold().blink());
+    println!(
+        "{}",
+        format!(
+            "{} ({}, sent as {} Hz mono)",
+            recorder.device_name(),
+            recorder.input_format(),
+            TARGET_RATE
+        )
+        .dimmed()
+    );
+
+    // 2. Block until the user hits Enter or stops talking
+    io::stdout().flush().unwrap();
+    let recorder = if manual {
+        let mut pause = String::new();
+        io::stdin().read_line(&mut pause).unwrap();
+        recorder
+    } else {
+        // `listen` polls with sleeps; keep it off the async workers
+        let config = VadConfig::from_env();
+        let mut recorder = recorder;
+        let listened = tokio::task::spawn_blocking(move || {
+            let heard = recorder.listen(&config, |level, state| {
+                print!("\r{}", level_meter(level, state));
+                let _ = io::stdout().flush();
+            });
+            (recorder, heard)
+        })
+        .await;
+        println!();
+        let (recorder, heard) = match listened {
+            Ok(result) => result,
+            Err(e) => {
+                println!("{} {}", "Recording failed:".red(), e);
+                return None;
+            }
+        };
+        if !heard {
+            println!("{}", "No speech detected.".yellow());
+            recorder.stop();
+            return None;
+        }
+        recorder
+    };
+
+    // 3. Stop and encode, in memory
+    println!("{}", "Processing audio...".dimmed());
+    let wav = match recorder.stop_to_wav() {
+        Ok(wav) => wav,
+        Err(e) => {
+            println!("{} {}", "Audio encoding error:".red(), e);
+            return None;
+        }
+    };
+
+    // 4. Send to Cloud (Groq Whisper)
+    match groq_client.transcribe_audio(wav).await {
+        Ok(text) => {
+            println!("{} {}", "Transcribed:".green().bold(), text.italic());
+            Some(text)
+        }
+        Err(e) => {
+            println!("{} {}", "Transcription failed:".red(), e);
+            None
+        }
+    }
+}
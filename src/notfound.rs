@@ -0,0 +1,291 @@
+use crate::cmd;
+use std::{
+    collections::BTreeSet, env, fs, os::unix::fs::PermissionsExt, path::Path, process::Command,
+};
+
+const MAX_MATCHES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+}
+
+impl PackageManager {
+    pub fn install_command(self, package: &str) -> String {
+        match self {
+            PackageManager::Apt => format!("sudo apt install {}", package),
+            PackageManager::Dnf => format!("sudo dnf install {}", package),
+            PackageManager::Pacman => format!("sudo pacman -S {}", package),
+        }
+    }
+}
+
+/// Extract the program name from "command not found" errors:
+///   sh: 1: gti: not found
+///   bash: gti: command not found
+///   sh: line 1: gti: command not found
+///   zsh: command not found: gti
+pub fn extract_missing_command(stderr: &str) -> Option<String> {
+    for line in stderr.lines() {
+        if let Some(pos) = line.find("command not found: ") {
+            let name = line[pos + "command not found: ".len()..].trim();
+            if !name.is_empty() {
+                return Some(name.to_string());
+            }
+        }
+
+        let parts: Vec<&str> = line.split(':').map(str::trim).collect();
+        if let Some(i) = parts
+            .iter()
+            .position(|p| *p == "not found" || *p == "command not found")
+            && i > 0
+            && !parts[i - 1].is_empty()
+        {
+            return Some(parts[i - 1].to_string());
+        }
+    }
+    None
+}
+
+/// Fixed commands for a program that does not exist: close matches on `PATH`
+/// first, otherwise the install command for the package that provides it
+pub fn suggest(cmd: &str, name: &str) -> Vec<String> {
+    let matches = path_matches(name);
+    if !matches.is_empty() {
+        return matches
+            .iter()
+            .filter_map(|m| replace_word(cmd, name, m))
+            .collect();
+    }
+
+    match find_package(name) {
+        Some((manager, package)) => vec![manager.install_command(&package)],
+        None => Vec::new(),
+    }
+}
+
+/// Executables on `PATH` within typo distance of `name`, closest first
+pub fn path_matches(name: &str) -> Vec<String> {
+    let path = env::var("PATH").unwrap_or_default();
+    let mut seen = BTreeSet::new();
+    let mut matches = Vec::new();
+
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let candidate = entry.file_name().to_string_lossy().to_string();
+            if candidate == name || !seen.insert(candidate.clone()) {
+                continue;
+            }
+            let d = cmd::edit_distance(name, &candidate);
+            if d <= cmd::max_typos(name) && is_executable(&entry.path()) {
+                matches.push((d, candidate));
+            }
+        }
+    }
+
+    matches.sort();
+    matches.truncate(MAX_MATCHES);
+    matches.into_iter().map(|(_, m)| m).collect()
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Ask the locally available package databases which package ships `name`.
+/// Only cache-backed lookups are used, so this never touches the network.
+pub fn find_package(name: &str) -> Option<(PackageManager, String)> {
+    // Debian/Ubuntu: the same helper bash uses for its own hint. It prints the
+    // hint on stderr, so this one is read whole and parsed strictly.
+    if let Ok(out) = Command::new("/usr/lib/command-not-found")
+        .args(["--ignore-installed", name])
+        .output()
+    {
+        let mut text = String::from_utf8_lossy(&out.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&out.stderr));
+        if let Some(package) = parse_command_not_found(&text) {
+            return Some((PackageManager::Apt, package));
+        }
+    }
+
+    let bin_regex = format!("/s?bin/{}$", name);
+    if let Some(package) =
+        run("apt-file", &["search", "--regexp", &bin_regex]).and_then(|o| parse_apt_file(&o))
+    {
+        return Some((PackageManager::Apt, package));
+    }
+
+    let bin_glob = format!("*/bin/{}", name);
+    let dnf_args = [
+        "-C",
+        "-q",
+        "repoquery",
+        "--qf",
+        "%{name}",
+        "--whatprovides",
+        &bin_glob,
+    ];
+    if let Some(package) = run("dnf", &dnf_args).and_then(|o| parse_dnf(&o)) {
+        return Some((PackageManager::Dnf, package));
+    }
+
+    let usr_bin = format!("/usr/bin/{}", name);
+    for (program, args) in [
+        ("pacman", vec!["-Fq", usr_bin.as_str()]),
+        ("pkgfile", vec!["--binaries", name]),
+    ] {
+        if let Some(package) = run(program, &args).and_then(|o| parse_pacman(&o)) {
+            return Some((PackageManager::Pacman, package));
+        }
+    }
+
+    None
+}
+
+/// "sudo apt install git" in command-not-found's hint
+fn parse_command_not_found(out: &str) -> Option<String> {
+    out.lines()
+        .filter_map(|l| l.trim().strip_prefix("sudo apt install "))
+        .map(str::trim)
+        .find(|p| is_package_name(p))
+        .map(str::to_string)
+}
+
+/// "git: /usr/bin/git"
+fn parse_apt_file(out: &str) -> Option<String> {
+    out.lines()
+        .filter_map(|l| l.split_once(": /"))
+        .map(|(package, _)| package.trim())
+        .find(|p| is_package_name(p))
+        .map(str::to_string)
+}
+
+/// One package name per line, from `--qf %{name}`
+fn parse_dnf(out: &str) -> Option<String> {
+    out.lines()
+        .map(str::trim)
+        .find(|l| is_package_name(l))
+        .map(str::to_string)
+}
+
+/// "extra/git" from `pacman -Fq` and `pkgfile --binaries`
+fn parse_pacman(out: &str) -> Option<String> {
+    out.lines()
+        .map(str::trim)
+        .filter_map(|l| l.split_once('/'))
+        .map(|(_, package)| package.trim())
+        .find(|p| is_package_name(p))
+        .map(str::to_string)
+}
+
+/// Package names across apt, dnf and pacman: no spaces or punctuation beyond
+/// `+-._@`, starting with a letter or digit. Keeps error lines out.
+fn is_package_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_' | '@'))
+}
+
+/// Run a lookup tool, returning its stdout if it succeeded and printed anything
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout).to_string();
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Replace the first whole-word occurrence of `word` in `cmd`
+fn replace_word(cmd: &str, word: &str, with: &str) -> Option<String> {
+    let is_boundary = |c: Option<char>| {
+        c.is_none_or(|c| {
+            c.is_whitespace() || matches!(c, '|' | '&' | ';' | '(' | ')' | '`' | '"' | '\'')
+        })
+    };
+
+    for (pos, _) in cmd.match_indices(word) {
+        let before = cmd[..pos].chars().next_back();
+        let after = cmd[pos + word.len()..].chars().next();
+        if is_boundary(before) && is_boundary(after) {
+            return Some(format!(
+                "{}{}{}",
+                &cmd[..pos],
+                with,
+                &cmd[pos + word.len()..]
+            ));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_not_found_hint() {
+        let out =
+            "\nCommand 'htop' not found, but can be installed with:\n\nsudo apt install htop\n\n";
+        assert_eq!(parse_command_not_found(out).as_deref(), Some("htop"));
+
+        let several = "Command 'convert' not found, but can be installed with:\n\n\
+            sudo apt install graphicsmagick-imagemagick-compat  # version 1.4\n\
+            sudo apt install imagemagick-6.q16                  # version 8:6.9\n";
+        // The version comments make these lines ambiguous; better no hint than a wrong one
+        assert_eq!(parse_command_not_found(several), None);
+
+        assert_eq!(
+            parse_command_not_found("Command 'qwzx' not found, did you mean:\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn apt_file_output() {
+        assert_eq!(
+            parse_apt_file("git: /usr/bin/git\ngit-man: /usr/share/man/man1/git.1.gz\n").as_deref(),
+            Some("git")
+        );
+        assert_eq!(
+            parse_apt_file("E: The cache is empty. You need to run \"apt-file update\" first.\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn dnf_output() {
+        assert_eq!(parse_dnf("git-core\n").as_deref(), Some("git-core"));
+        assert_eq!(
+            parse_dnf("Cache-only enabled but no cache for 'fedora'\n"),
+            None
+        );
+        assert_eq!(parse_dnf("\n\n"), None);
+    }
+
+    #[test]
+    fn pacman_output() {
+        assert_eq!(parse_pacman("extra/git\n").as_deref(), Some("git"));
+        assert_eq!(
+            parse_pacman("core/coreutils\n").as_deref(),
+            Some("coreutils")
+        );
+        assert_eq!(
+            parse_pacman(
+                "warning: database file for 'core' does not exist (use '-Fy' to download)\n"
+            ),
+            None
+        );
+    }
+}
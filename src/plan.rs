@@ -0,0 +1,300 @@
+use crate::agent::{StepBudget, StopReason};
+use crate::cli::Approval;
+use crate::cmd::{self, Builtin};
+use crate::command_policy::{self, CommandRisk};
+use crate::edit::{self, ask, rejection_reason};
+use colored::*;
+use std::path::PathBuf;
+
+pub struct Plan {
+    pub goal: String,
+    pub steps: Vec<PlanStep>,
+}
+
+pub struct PlanStep {
+    pub cmd: String,
+    pub why: String,
+    pub status: StepStatus,
+}
+
+#[derive(PartialEq)]
+pub enum StepStatus {
+    Pending,
+    Done,
+    Failed(i32),
+    Skipped,
+}
+
+/// Result of running a plan, fed back to the model as `PLAN_RESULT`
+pub struct PlanReport {
+    pub ai_view: String,
+    pub ran_any: bool,
+    pub stop: Option<StopReason>,
+    pub rejected: Option<String>,
+    /// The user quit a step-by-step run partway
+    pub quit: bool,
+    /// Steps the user edited before running: (original, edited)
+    pub edits: Vec<(String, String)>,
+}
+
+/// Collect the `PLAN:` goal and its `STEP:` / `WHY:` pairs from a reply, in order.
+/// Returns None if the reply has no plan or the plan has no steps.
+pub fn parse_plan(reply: &str) -> Option<Plan> {
+    let mut goal: Option<String> = None;
+    let mut steps: Vec<PlanStep> = Vec::new();
+
+    for line in reply.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("PLAN:") {
+            goal = Some(rest.trim().to_string());
+        } else if let Some(rest) = line.strip_prefix("STEP:") {
+            steps.push(PlanStep {
+                cmd: rest.trim().to_string(),
+                why: String::new(),
+                status: StepStatus::Pending,
+            });
+        } else if let Some(rest) = line.strip_prefix("WHY:")
+            && let Some(step) = steps.last_mut()
+        {
+            step.why = rest.trim().to_string();
+        }
+    }
+
+    steps.retain(|s| !s.cmd.is_empty());
+    if steps.is_empty() {
+        return None;
+    }
+    Some(Plan { goal: goal?, steps })
+}
+
+pub fn print_checklist(plan: &Plan) {
+    println!("{} {}", "Plan:".bold().yellow(), plan.goal);
+    for (i, step) in plan.steps.iter().enumerate() {
+        let mark = match step.status {
+            StepStatus::Pending => "[ ]".normal(),
+            StepStatus::Done => "[✔]".green(),
+            StepStatus::Failed(_) => "[✖]".red(),
+            StepStatus::Skipped => "[-]".dimmed(),
+        };
+        println!("  {} {}. {}", mark, i + 1, step.cmd.cyan());
+        if !step.why.is_empty() {
+            println!("        {}", step.why.dimmed());
+        }
+    }
+}
+
+/// Ask for approval, then run the steps in order, stopping on the first failure
+pub fn run_plan(
+    plan: &mut Plan,
+    current_dir: &mut PathBuf,
+    has_display: bool,
+    budget: &mut StepBudget,
+    approval: Approval,
+) -> PlanReport {
+    let mut report = PlanReport {
+        ai_view: String::new(),
+        ran_any: false,
+        stop: None,
+        rejected: None,
+        quit: false,
+        edits: Vec::new(),
+    };
+
+    let answer = match approval {
+        Approval::Yes => "a".to_string(),
+        _ => ask("Run plan? [a]ll / [s]tep by step / [n]o (n: <reason>): "),
+    };
+    if let Some(reason) = rejection_reason(&answer) {
+        report.rejected = Some(reason);
+        return report;
+    }
+
+    let step_by_step = match answer.to_lowercase().as_str() {
+        "a" | "all" => false,
+        "s" | "step" => true,
+        _ => {
+            println!("{}", "Cancelled.".dimmed());
+            return report;
+        }
+    };
+
+    for (i, step) in plan.steps.iter_mut().enumerate() {
+        if step_by_step {
+            println!(
+                "{} {}",
+                format!("Step {}:", i + 1).bold().yellow(),
+                step.cmd.cyan()
+            );
+            // A cancelled or emptied edit asks again; it never counts as a yes
+            let choice = loop {
+                match ask("Run? [y]es / [e]dit / [k] skip / [q]uit: ")
+                    .to_lowercase()
+                    .as_str()
+                {
+                    "y" | "yes" => break "y",
+                    "e" | "edit" => match edit::edit_inline(&step.cmd) {
+                        Some(edited) => {
+                            let original = std::mem::replace(&mut step.cmd, edited);
+                            report.edits.push((original, step.cmd.clone()));
+                            break "y";
+                        }
+                        None => println!("{}", "Edit cancelled.".dimmed()),
+                    },
+                    "k" | "skip" => break "k",
+                    _ => break "q",
+                }
+            };
+            match choice {
+                "y" => {}
+                "k" => {
+                    step.status = StepStatus::Skipped;
+                    report.ai_view.push_str(&format!(
+                        "step {}: skipped by user: {}\n",
+                        i + 1,
+                        step.cmd
+                    ));
+                    continue;
+                }
+                _ => {
+                    report.quit = true;
+                    break;
+                }
+            }
+        }
+
+        if approval == Approval::Yes
+            && matches!(
+                command_policy::classify_command(&step.cmd),
+                CommandRisk::Dangerous
+            )
+        {
+            println!(
+                "{}",
+                "Refusing to auto-run a dangerous command.".red().bold()
+            );
+            report.ai_view.push_str(&format!(
+                "step {}: refused, dangerous command: {}\n",
+                i + 1,
+                step.cmd
+            ));
+            break;
+        }
+
+        if let Some(reason) = budget.check_command(&step.cmd) {
+            report.stop = Some(reason);
+            break;
+        }
+
+        report.ran_any = true;
+
+        match cmd::run_builtin(&step.cmd, current_dir, has_display) {
+            None => {}
+            Some(Builtin::NoDisplay) => {
+                step.status = StepStatus::Failed(-1);
+                report.ai_view.push_str(&format!(
+                    "step {}: blocked, no graphical display: {}\n",
+                    i + 1,
+                    step.cmd
+                ));
+                break;
+            }
+            Some(Builtin::ChangedDir(dir)) => {
+                step.status = StepStatus::Done;
+                println!("{} {}", "Directory changed to".green(), dir.display());
+                report.ai_view.push_str(&format!(
+                    "step {}: changed directory to {}\n",
+                    i + 1,
+                    dir.display()
+                ));
+                continue;
+            }
+            Some(Builtin::CdFailed(target)) => {
+                step.status = StepStatus::Failed(1);
+                println!("{} {}", "cd failed:".red(), target.display());
+                report.ai_view.push_str(&format!(
+                    "step {}: cd failed: {}\n",
+                    i + 1,
+                    target.display()
+                ));
+                break;
+            }
+        }
+
+        let result = cmd::execute_and_capture(&step.cmd, current_dir);
+        println!("{}", result.user_view);
+        report
+            .ai_view
+            .push_str(&format!("step {}:\n{}\n", i + 1, result.ai_view));
+
+        let stop = budget.record(&step.cmd, result.exit_code);
+
+        if result.exit_code != 0 {
+            step.status = StepStatus::Failed(result.exit_code);
+            report.stop = stop;
+            break;
+        }
+        step.status = StepStatus::Done;
+
+        if stop.is_some() {
+            report.stop = stop;
+            break;
+        }
+    }
+
+    for (i, step) in plan.steps.iter().enumerate() {
+        if step.status == StepStatus::Pending {
+            report
+                .ai_view
+                .push_str(&format!("step {}: not run: {}\n", i + 1, step.cmd));
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn why_belongs_to_the_step_before_it() {
+        let reply = "PLAN: set up the project\n\
+                     STEP: mkdir build\n\
+                     WHY: out-of-tree build\n\
+                     STEP: cd build\n\
+                     STEP: cmake ..\n\
+                     WHY: generate makefiles";
+        let plan = parse_plan(reply).unwrap();
+        assert_eq!(plan.goal, "set up the project");
+        let steps: Vec<(&str, &str)> = plan
+            .steps
+            .iter()
+            .map(|s| (s.cmd.as_str(), s.why.as_str()))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("mkdir build", "out-of-tree build"),
+                ("cd build", ""),
+                ("cmake ..", "generate makefiles"),
+            ]
+        );
+        assert!(plan.steps.iter().all(|s| s.status == StepStatus::Pending));
+    }
+
+    #[test]
+    fn empty_steps_are_dropped() {
+        let plan = parse_plan("PLAN: tidy\nSTEP:\nWHY: nothing\nSTEP: rm -f *.o").unwrap();
+        assert_eq!(plan.steps.len(), 1);
+        assert_eq!(plan.steps[0].cmd, "rm -f *.o");
+        assert_eq!(plan.steps[0].why, "");
+
+        assert!(parse_plan("PLAN: nothing to do\nSTEP:   ").is_none());
+    }
+
+    #[test]
+    fn steps_without_a_goal_are_not_a_plan() {
+        assert!(parse_plan("STEP: ls\nSTEP: pwd").is_none());
+        assert!(parse_plan("CMD: ls").is_none());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::meta;
+use rustyline::{
+    Config, Context, Editor, Helper,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::FileHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Line editor for the main prompt: history in the data dir, Ctrl-R search,
+/// multi-line input and completion of paths and `:` commands
+pub struct Repl {
+    editor: Editor<ReplHelper, FileHistory>,
+    history_path: Option<PathBuf>,
+}
+
+impl Repl {
+    pub fn new() -> Result<Self, ReadlineError> {
+        let config = Config::builder()
+            .max_history_size(1000)?
+            .history_ignore_dups(true)?
+            .history_ignore_space(true)
+            .auto_add_history(false)
+            .build();
+
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(ReplHelper {
+            cwd: PathBuf::from("."),
+        }));
+
+        let history_path = dirs_next::data_dir().map(|d| d.join("ai-terminal").join("history.txt"));
+        if let Some(path) = &history_path {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = editor.load_history(path);
+        }
+
+        Ok(Self {
+            editor,
+            history_path,
+        })
+    }
+
+    /// Read one (possibly multi-line) entry. Returns None on Ctrl-D.
+    /// Ctrl-C discards the current line and returns an empty string.
+    pub fn read_line(&mut self, prompt: &str, cwd: &Path) -> Option<String> {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.cwd = cwd.to_path_buf();
+        }
+
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                let line = line.replace("\\\n", "");
+                if !line.trim().is_empty() {
+                    let _ = self.editor.add_history_entry(line.as_str());
+                    if let Some(path) = &self.history_path {
+                        let _ = self.editor.append_history(path);
+                    }
+                }
+                Some(line)
+            }
+            Err(ReadlineError::Interrupted) => Some(String::new()),
+            Err(_) => None,
+        }
+    }
+}
+
+struct ReplHelper {
+    cwd: PathBuf,
+}
+
+impl Helper for ReplHelper {}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {
+    /// A trailing `\` continues the line; `"""` opens a block (e.g. pasted logs) until closed
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        if input.ends_with('\\') || input.matches("\"\"\"").count() % 2 == 1 {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+
+        // Meta-commands only make sense as the first word
+        if start == 0 && word.starts_with(':') {
+            let matches = meta::names()
+                .filter(|c| c.starts_with(word))
+                .map(|c| Pair {
+                    display: c.to_string(),
+                    replacement: c.to_string(),
+                })
+                .collect();
+            return Ok((0, matches));
+        }
+
+        // `@path` attaches a file to the prompt
+        if let Some(path) = word.strip_prefix('@') {
+            return Ok((start + 1, complete_path(path, &self.cwd)));
+        }
+
+        Ok((start, complete_path(word, &self.cwd)))
+    }
+}
+
+/// Complete `word` as a path relative to `cwd` (or absolute / `~`-prefixed)
+fn complete_path(word: &str, cwd: &Path) -> Vec<Pair> {
+    let (dir_part, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+
+    let base = if let Some(rest) = dir_part.strip_prefix("~/") {
+        dirs_next::home_dir().unwrap_or_default().join(rest)
+    } else if dir_part.is_empty() {
+        cwd.to_path_buf()
+    } else {
+        cwd.join(dir_part)
+    };
+
+    let Ok(entries) = fs::read_dir(&base) else {
+        return Vec::new();
+    };
+
+    let mut matches: Vec<Pair> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Hide dotfiles unless explicitly asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let suffix = if entry.path().is_dir() { "/" } else { "" };
+            Some(Pair {
+                display: format!("{}{}", name, suffix),
+                replacement: format!("{}{}{}", dir_part, name, suffix),
+            })
+        })
+        .collect();
+
+    matches.sort_by(|a, b| a.display.cmp(&b.display));
+    matches
+}
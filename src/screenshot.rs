@@ -0,0 +1,178 @@
+use crate::attach;
+use crate::cmdline;
+use crate::sys;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Programs that take screenshots. Matched against parsed program names, so
+/// `python -c "import os"` is not mistaken for ImageMagick's `import`.
+const TOOLS: &[&str] = &[
+    "grim",
+    "spectacle",
+    "flameshot",
+    "gnome-screenshot",
+    "maim",
+    "scrot",
+    "import",
+    "xwd",
+    "screencapture",
+];
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+pub fn is_screenshot_command(cmd: &str) -> bool {
+    cmdline::programs(cmd)
+        .iter()
+        .any(|p| TOOLS.contains(&p.as_str()))
+}
+
+/// The image file a screenshot command names on its command line, if it exists
+/// (`grim shot.png`, `scrot -o /tmp/s.png`)
+pub fn output_path(cmd: &str, cwd: &Path) -> Option<PathBuf> {
+    cmdline::segments(cmd)
+        .iter()
+        .filter(|s| s.program().is_some_and(|p| TOOLS.contains(&p)))
+        .flat_map(|s| s.words.iter().chain(s.redirects.iter().map(|(_, t)| t)))
+        .filter(|w| {
+            Path::new(w.as_str())
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| IMAGE_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+        })
+        .map(|w| attach::resolve(w, cwd))
+        .find(|p| p.is_file())
+}
+
+/// A fresh file name in the private temp dir for the next capture
+fn temp_path() -> Result<PathBuf, String> {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let dir = sys::private_temp_dir().map_err(|e| format!("temp dir: {}", e))?;
+    Ok(dir.join(format!("screenshot-{}.png", stamp)))
+}
+
+/// Capture the whole screen with the first tool available for the current
+/// display server and return the path of the PNG it wrote
+pub fn capture() -> Result<PathBuf, String> {
+    let (has_display, is_wayland, is_x11) = sys::detect_display();
+    if !has_display && !cfg!(target_os = "macos") {
+        return Err("no graphical display".into());
+    }
+
+    let path = temp_path()?;
+    let out = path.display().to_string();
+
+    // (program that must exist, full command line)
+    let candidates: Vec<(&str, Vec<String>)> = if cfg!(target_os = "macos") {
+        vec![("screencapture", args(&["-x", &out]))]
+    } else if is_wayland {
+        vec![
+            ("grim", args(&[&out])),
+            ("spectacle", args(&["-b", "-n", "-f", "-o", &out])),
+            ("gnome-screenshot", args(&["-f", &out])),
+            ("flameshot", args(&["full", "-p", &out])),
+        ]
+    } else if is_x11 {
+        vec![
+            ("maim", args(&[&out])),
+            ("scrot", args(&["-o", &out])),
+            ("import", args(&["-window", "root", &out])),
+            ("gnome-screenshot", args(&["-f", &out])),
+            ("spectacle", args(&["-b", "-n", "-f", "-o", &out])),
+            ("flameshot", args(&["full", "-p", &out])),
+            // xwd only writes its own format; ImageMagick converts it
+            (
+                "xwd",
+                args(&[
+                    "-c",
+                    &format!("xwd -root -silent | convert xwd:- png:'{}'", out),
+                ]),
+            ),
+        ]
+    } else {
+        Vec::new()
+    };
+
+    let mut tried = Vec::new();
+    for (tool, tool_args) in candidates {
+        if !sys::on_path(tool) {
+            continue;
+        }
+        tried.push(tool);
+
+        let program = if tool == "xwd" { "sh" } else { tool };
+        let status = Command::new(program).args(&tool_args).status();
+        if status.is_ok_and(|s| s.success()) && fs::metadata(&path).is_ok_and(|m| m.len() > 0) {
+            return Ok(path);
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    if tried.is_empty() {
+        let wanted = if is_wayland {
+            "grim, spectacle, gnome-screenshot or flameshot"
+        } else {
+            "maim, scrot, import, gnome-screenshot, spectacle, flameshot or xwd"
+        };
+        Err(format!("no screenshot tool found (install {})", wanted))
+    } else {
+        Err(format!("capture failed (tried {})", tried.join(", ")))
+    }
+}
+
+/// Delete a capture once it has been read, if it is one of ours in the private
+/// temp dir. Files the user named themselves are left alone.
+pub fn discard(path: &Path) {
+    if let Ok(dir) = sys::private_temp_dir()
+        && path.starts_with(&dir)
+    {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_tools_are_matched_by_program() {
+        assert!(!is_screenshot_command("python -c \"import os\""));
+        assert!(!is_screenshot_command("echo grim"));
+        assert!(is_screenshot_command("grim"));
+        assert!(is_screenshot_command("sudo maim x.png"));
+        assert!(is_screenshot_command("foo | flameshot full"));
+    }
+
+    #[test]
+    fn output_path_finds_the_named_file() {
+        let dir =
+            std::env::temp_dir().join(format!("ai-terminal-test-shot-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("shot.png"), "png").unwrap();
+
+        assert_eq!(
+            output_path("scrot -o shot.png", &dir),
+            Some(dir.join("shot.png"))
+        );
+        assert_eq!(
+            output_path("import -window root png:- > shot.png", &dir),
+            Some(dir.join("shot.png"))
+        );
+        // Not written, or not by a screenshot tool
+        assert_eq!(output_path("scrot -o missing.png", &dir), None);
+        assert_eq!(output_path("cp shot.png copy.png", &dir), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::cli::{Approval, Shell, exit};
+use crate::cmd;
+use crate::command_policy::{self, CommandRisk};
+use crate::edit::ask;
+use crate::groq::{GroqClient, Message};
+use crate::handler;
+use crate::plan;
+use colored::*;
+use std::{env, fs, path::PathBuf};
+
+/// The command the user ran last in their shell, as recorded by the shell-init hook
+pub struct LastCommand {
+    pub exit_code: i32,
+    pub cwd: PathBuf,
+    pub command: String,
+}
+
+/// Where the hook records the last command: exit code, cwd and command, one per line
+pub fn state_path() -> Option<PathBuf> {
+    dirs_next::data_dir().map(|d| d.join("ai-terminal").join("last_command"))
+}
+
+pub fn load_last_command() -> Option<LastCommand> {
+    let content = fs::read_to_string(state_path()?).ok()?;
+    let mut lines = content.splitn(3, '\n');
+
+    let exit_code = lines.next()?.trim().parse().ok()?;
+    let cwd = PathBuf::from(lines.next()?);
+    let command = lines.next()?.trim().to_string();
+
+    if command.is_empty() {
+        None
+    } else {
+        Some(LastCommand {
+            exit_code,
+            cwd,
+            command,
+        })
+    }
+}
+
+/// Print the widget and hook for `eval "$(ai-terminal shell-init bash)"`.
+/// Alt-A sends the current command line to `ai-terminal suggest` and replaces it
+/// with the proposed command; a line starting with `#` is treated as a plain request.
+/// The hook records every command's exit code for `ai-terminal why`. It leaves the
+/// shell's own stderr alone, so `why` re-runs the command to see its output.
+pub fn init_script(shell: Shell) -> String {
+    let exe = env::current_exe()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "ai-terminal".into());
+    let exe = shell_quote(&exe);
+
+    let state = state_path().unwrap_or_else(|| env::temp_dir().join("ai-terminal-last-command"));
+    if let Some(parent) = state.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let state = shell_quote(&state.display().to_string());
+
+    match shell {
+        Shell::Bash => format!(
+            r#"__ai_terminal_record() {{
+    local code=$?
+    local cmd
+    cmd=$(HISTTIMEFORMAT= builtin history 1)
+    cmd=${{cmd#*[0-9]  }}
+    printf '%s\n%s\n%s\n' "$code" "$PWD" "$cmd" > {state} 2>/dev/null
+    return $code
+}}
+PROMPT_COMMAND="__ai_terminal_record${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}"
+
+__ai_terminal_widget() {{
+    local suggestion
+    suggestion=$({exe} suggest -- "$READLINE_LINE") || return
+    READLINE_LINE=$suggestion
+    READLINE_POINT=${{#READLINE_LINE}}
+}}
+bind -x '"\ea": __ai_terminal_widget'
+"#
+        ),
+        Shell::Zsh => format!(
+            r#"__ai_terminal_preexec() {{ __ai_terminal_last=$1 }}
+__ai_terminal_record() {{
+    local code=$?
+    [[ -n $__ai_terminal_last ]] || return
+    printf '%s\n%s\n%s\n' "$code" "$PWD" "$__ai_terminal_last" >| {state} 2>/dev/null
+}}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __ai_terminal_preexec
+add-zsh-hook precmd __ai_terminal_record
+
+__ai_terminal_widget() {{
+    local suggestion
+    suggestion=$({exe} suggest -- "$BUFFER") || {{ zle reset-prompt; return }}
+    BUFFER=$suggestion
+    CURSOR=${{#BUFFER}}
+    zle reset-prompt
+}}
+zle -N __ai_terminal_widget
+bindkey '\ea' __ai_terminal_widget
+"#
+        ),
+        Shell::Fish => format!(
+            r#"function __ai_terminal_record --on-event fish_postexec
+    set -l code $status
+    printf '%s\n%s\n%s\n' $code $PWD "$argv" > {state} 2>/dev/null
+end
+
+function __ai_terminal_widget
+    set -l suggestion ({exe} suggest -- (commandline) | string collect)
+    and commandline -r -- $suggestion
+    commandline -f repaint
+end
+bind \ea __ai_terminal_widget
+"#
+        ),
+    }
+}
+
+/// Ask the model for a single command for `line` and print it on stdout.
+/// The model's explanation goes to stderr so it shows up without landing in the buffer.
+/// Nothing is executed.
+pub async fn suggest(groq_client: &GroqClient, system: Message, line: &str) -> u8 {
+    let line = line.trim();
+    if line.is_empty() {
+        return exit::USAGE;
+    }
+
+    let request = match line.strip_prefix('#') {
+        Some(rest) => rest.trim().to_string(),
+        None => format!(
+            "Fix, complete or improve this shell command and reply with a single CMD:\n{}",
+            line
+        ),
+    };
+
+    let history = vec![
+        system,
+        Message {
+            role: "user".into(),
+            content: request.into(),
+        },
+    ];
+
+    let reply = match groq_client.chat(history).await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red(), e);
+            return exit::API_ERROR;
+        }
+    };
+
+    let parsed = handler::parse_reply(&reply);
+    if !parsed.msg.is_empty() {
+        eprintln!("{} {}", "AI:".bold().green(), parsed.msg);
+    }
+
+    // A plan collapses into one line so it still fits in the shell buffer
+    let cmd = match plan::parse_plan(&reply) {
+        Some(plan) => plan
+            .steps
+            .iter()
+            .map(|s| s.cmd.as_str())
+            .collect::<Vec<_>>()
+            .join(" && "),
+        None => parsed.cmd,
+    };
+
+    if cmd.is_empty() {
+        return exit::COMMAND_FAILED;
+    }
+
+    println!("{}", cmd);
+    exit::SUCCESS
+}
+
+/// What `ai-terminal why` leaves for the caller to do
+pub enum Why {
+    /// Ask the model this, in this directory
+    Ask { prompt: String, cwd: PathBuf },
+    /// Nothing left to ask; exit with this code
+    Done(u8),
+}
+
+/// `ai-terminal why`: look at the last failed shell command, re-run it to capture
+/// its output if the user agrees, and try the local `suggest_fix` heuristics
+/// before involving the model.
+pub fn why(approval: Approval) -> Why {
+    let Some(last) = load_last_command() else {
+        eprintln!(
+            "{} no recorded command. Add `eval \"$(ai-terminal shell-init bash)\"` to your shell rc.",
+            "Error:".red()
+        );
+        return Why::Done(exit::USAGE);
+    };
+
+    if last.exit_code == 0 {
+        println!(
+            "{} {}",
+            "Last command succeeded:".green(),
+            last.command.cyan()
+        );
+        return Why::Done(exit::SUCCESS);
+    }
+
+    println!(
+        "{} {} {}",
+        "Last command:".bold(),
+        last.command.cyan(),
+        format!("(exit {})", last.exit_code).red()
+    );
+
+    // 1. A re-run the user agrees to, for the output
+    let (output, suggestions) = if confirm_rerun(&last.command, approval) {
+        let result = cmd::execute_and_capture(&last.command, &last.cwd);
+        println!("{}", result.user_view);
+        if result.exit_code == 0 {
+            println!("{}", "It succeeds now; nothing to diagnose.".green());
+            return Why::Done(exit::SUCCESS);
+        }
+        (result.ai_view, result.suggestions)
+    } else {
+        ("(output not captured)".to_string(), Vec::new())
+    };
+
+    // 2. A local fix before the model
+    if !suggestions.is_empty() {
+        if approval != Approval::Ask {
+            handler::print_suggestions(&suggestions);
+            return Why::Done(exit::SUCCESS);
+        }
+        if let Some(fix) = handler::offer_fix(&suggestions) {
+            let fixed = cmd::execute_and_capture(&fix, &last.cwd);
+            println!("{}", fixed.user_view);
+            return Why::Done(if fixed.exit_code == 0 {
+                exit::SUCCESS
+            } else {
+                exit::COMMAND_FAILED
+            });
+        }
+        if !ask("Ask the AI anyway? (y/n): ").eq_ignore_ascii_case("y") {
+            return Why::Done(exit::SUCCESS);
+        }
+    }
+
+    let prompt = format!(
+        "WHY_FAILED:\ncommand: {}\nexit_code: {}\ncwd: {}\nCOMMAND_OUTPUT:\n{}\n\nExplain why this command failed and propose a fix.",
+        last.command,
+        last.exit_code,
+        last.cwd.display(),
+        output
+    );
+
+    Why::Ask {
+        prompt,
+        cwd: last.cwd,
+    }
+}
+
+/// Whether to re-run `command` to see its output. `-y` only skips the question
+/// for safe commands; a dangerous one needs an explicit `yes`.
+fn confirm_rerun(command: &str, approval: Approval) -> bool {
+    let risk = command_policy::classify_command(command);
+    match (approval, risk) {
+        (Approval::NoExec, _) => false,
+        (Approval::Yes, CommandRisk::Safe) => true,
+        (_, CommandRisk::Dangerous) => {
+            println!("{} {}", CommandRisk::Dangerous, command.cyan());
+            ask("Type 'yes' to re-run it: ").eq_ignore_ascii_case("yes")
+        }
+        (_, risk) => {
+            if risk == CommandRisk::Caution {
+                println!("{} {}", CommandRisk::Caution, command.cyan());
+            }
+            ask("Re-run it to capture the output? (y/n): ").eq_ignore_ascii_case("y")
+        }
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
@@ -1,6 +1,15 @@
-use std::{env, path::PathBuf};
+use crate::cmd;
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read},
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
 
-pub fn gather_info(cwd: &PathBuf, display: bool, wayland: bool, x11: bool) -> String {
+/// How much of the end of a pipe we hold before truncating for the model
+const MAX_PIPED_READ: usize = 1024 * 1024;
+
+pub fn gather_info(cwd: &Path, display: bool, wayland: bool, x11: bool) -> String {
     format!(
         "\
         OS: {}
@@ -24,3 +33,67 @@ pub fn detect_display() -> (bool, bool, bool) {
     let is_x11 = env::var("DISPLAY").is_ok() && !is_wayland;
     (has_display, is_wayland, is_x11)
 }
+
+/// Read content piped into the process (`journalctl | ai-terminal "..."`).
+/// Keeps the end of long input, where logs put the error.
+/// Returns None when stdin is a terminal or the pipe is empty.
+pub fn read_piped_input() -> Option<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return None;
+    }
+
+    // Only the tail is kept, so read the whole pipe without holding all of it
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 64 * 1024];
+    let mut lock = stdin.lock();
+    loop {
+        match lock.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => bytes.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+        if bytes.len() > 2 * MAX_PIPED_READ {
+            bytes.drain(..bytes.len() - MAX_PIPED_READ);
+        }
+    }
+
+    let text = String::from_utf8_lossy(&bytes);
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(cmd::truncate_tail(text.trim_end()))
+    }
+}
+
+/// Whether `program` exists in a `PATH` directory
+pub fn on_path(program: &str) -> bool {
+    let path = env::var("PATH").unwrap_or_default();
+    env::split_paths(&path).any(|dir| dir.join(program).is_file())
+}
+
+/// A temp directory only the current user can read, created on first use.
+/// Refuses a directory someone else owns or could write to, since the name
+/// is predictable.
+pub fn private_temp_dir() -> io::Result<PathBuf> {
+    let user = env::var("USER").unwrap_or_else(|_| "user".into());
+    let dir = env::temp_dir().join(format!("ai-terminal-{}", user));
+
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => return Ok(dir),
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        Err(_) => {}
+    }
+
+    let meta = fs::symlink_metadata(&dir)?;
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let euid = unsafe { libc::geteuid() };
+    if !meta.is_dir() || meta.uid() != euid || meta.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory we own", dir.display()),
+        ));
+    }
+    Ok(dir)
+}
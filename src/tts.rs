@@ -0,0 +1,348 @@
+use crate::groq;
+use crate::sys;
+use serde_json::json;
+use std::{
+    env,
+    future::Future,
+    io::Write,
+    pin::Pin,
+    process::{Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+const DEFAULT_TTS_URL: &str = "https://api.groq.com/openai/v1/audio/speech";
+const DEFAULT_TTS_MODEL: &str = "playai-tts";
+const DEFAULT_TTS_VOICE: &str = "Fritz-PlayAI";
+/// Local speech synthesizers, in order of preference
+const LOCAL_PROGRAMS: &[&str] = &["espeak-ng", "espeak"];
+
+/// A WAV file on its way, or why there isn't one
+type Synthesis<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + Send + 'a>>;
+
+/// Something that turns text into speech
+pub trait SpeechProvider: Send + Sync {
+    /// Shown by `:speak`
+    fn name(&self) -> String;
+    /// Speak `text` into a WAV file
+    fn synthesize<'a>(&'a self, text: &'a str) -> Synthesis<'a>;
+}
+
+/// An OpenAI-compatible `/audio/speech` endpoint: Groq by default, or OpenAI
+/// or a local server through `AI_TTS_URL`
+pub struct HttpSpeech {
+    http: reqwest::Client,
+    url: String,
+    api_key: String,
+    model: String,
+    voice: String,
+}
+
+impl HttpSpeech {
+    /// Reads `AI_TTS_URL`, `AI_TTS_MODEL`, `AI_TTS_VOICE` and `AI_TTS_API_KEY`
+    /// (falling back to `GROQ_API_KEY` for the Groq endpoint only, so the key
+    /// never goes to another host). None without a key.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("AI_TTS_URL").unwrap_or_else(|_| DEFAULT_TTS_URL.into());
+        let api_key = api_key_for(
+            &url,
+            env::var("AI_TTS_API_KEY").ok(),
+            env::var("GROQ_API_KEY").ok(),
+        )?;
+        Some(Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30)) // a stalled endpoint must not block the queue
+                .build()
+                .unwrap_or_default(),
+            url,
+            api_key,
+            model: env::var("AI_TTS_MODEL").unwrap_or_else(|_| DEFAULT_TTS_MODEL.into()),
+            voice: env::var("AI_TTS_VOICE").unwrap_or_else(|_| DEFAULT_TTS_VOICE.into()),
+        })
+    }
+}
+
+/// The TTS key if set; the Groq key only when talking to Groq
+fn api_key_for(url: &str, tts_key: Option<String>, groq_key: Option<String>) -> Option<String> {
+    tts_key.or(groq_key.filter(|_| url == DEFAULT_TTS_URL))
+}
+
+impl SpeechProvider for HttpSpeech {
+    fn name(&self) -> String {
+        let host = self
+            .url
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split('/').next())
+            .unwrap_or(&self.url);
+        format!("{} ({}) at {}", self.model, self.voice, host)
+    }
+
+    fn synthesize<'a>(&'a self, text: &'a str) -> Synthesis<'a> {
+        Box::pin(async move {
+            let res = self
+                .http
+                .post(&self.url)
+                .bearer_auth(&self.api_key)
+                .json(&json!({
+                    "model": self.model,
+                    "voice": self.voice,
+                    "input": text,
+                    "response_format": "wav",
+                }))
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let status = res.status();
+            if !status.is_success() {
+                let body = res.text().await.unwrap_or_default();
+                return Err(format!("{}: {}", status, body.trim()));
+            }
+            let wav = res.bytes().await.map_err(|e| e.to_string())?;
+            Ok(wav.to_vec())
+        })
+    }
+}
+
+/// `espeak-ng` or `espeak`, when installed
+pub struct CommandSpeech {
+    program: &'static str,
+}
+
+impl CommandSpeech {
+    pub fn find() -> Option<Self> {
+        LOCAL_PROGRAMS
+            .iter()
+            .find(|p| sys::on_path(p))
+            .map(|&program| Self { program })
+    }
+}
+
+impl SpeechProvider for CommandSpeech {
+    fn name(&self) -> String {
+        self.program.to_string()
+    }
+
+    fn synthesize<'a>(&'a self, text: &'a str) -> Synthesis<'a> {
+        let program = self.program;
+        let text = text.to_string();
+        Box::pin(async move {
+            // Text goes through stdin so a leading '-' is never read as an option
+            let run = move || -> std::io::Result<std::process::Output> {
+                let mut child = Command::new(program)
+                    .arg("--stdout")
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes())?;
+                }
+                child.wait_with_output()
+            };
+            let out = tokio::task::spawn_blocking(run)
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| format!("{}: {}", program, e))?;
+
+            if !out.status.success() || out.stdout.is_empty() {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                return Err(format!("{} failed: {}", program, stderr.trim()));
+            }
+            Ok(out.stdout)
+        })
+    }
+}
+
+/// Why a message was not heard: "Speech failed" or "Playback failed", and the reason
+pub type Failure = (&'static str, String);
+
+/// Speaks `MSG:` text in the background, one message after another.
+/// `stop` cuts off what is playing and drops anything still queued.
+pub struct Speaker {
+    pub enabled: bool,
+    providers: Arc<Vec<Box<dyn SpeechProvider>>>,
+    queue: mpsc::UnboundedSender<(u64, String)>,
+    /// Bumped by `stop`; queued or playing speech from an older generation ends
+    generation: Arc<AtomicU64>,
+    /// Held for the REPL to print, since the line editor owns the terminal
+    failures: Arc<Mutex<Vec<Failure>>>,
+}
+
+impl Speaker {
+    /// Providers come from `AI_TTS_PROVIDER` (`api`, `local`, or both by default,
+    /// the API first); `AI_TTS=1` turns speech on from the start.
+    /// Must be called inside the Tokio runtime.
+    pub fn from_env() -> Self {
+        let which = env::var("AI_TTS_PROVIDER").unwrap_or_default();
+        let mut providers: Vec<Box<dyn SpeechProvider>> = Vec::new();
+        if which != "local"
+            && let Some(http) = HttpSpeech::from_env()
+        {
+            providers.push(Box::new(http));
+        }
+        if which != "api"
+            && let Some(local) = CommandSpeech::find()
+        {
+            providers.push(Box::new(local));
+        }
+
+        let providers = Arc::new(providers);
+        let generation = Arc::new(AtomicU64::new(0));
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let (queue, rx) = mpsc::unbounded_channel();
+        tokio::spawn(speak_queue(
+            rx,
+            providers.clone(),
+            generation.clone(),
+            failures.clone(),
+        ));
+
+        Self {
+            enabled: env::var("AI_TTS").is_ok_and(|v| matches!(v.as_str(), "1" | "on" | "true")),
+            providers,
+            queue,
+            generation,
+            failures,
+        }
+    }
+
+    /// Names of the providers, in the order they are tried
+    pub fn provider_names(&self) -> Vec<String> {
+        self.providers.iter().map(|p| p.name()).collect()
+    }
+
+    /// Queue `text` to be spoken, if speech is on
+    pub fn say(&self, text: &str) {
+        let text = speakable(text);
+        if !self.enabled || text.is_empty() {
+            return;
+        }
+        let _ = self
+            .queue
+            .send((self.generation.load(Ordering::Relaxed), text));
+    }
+
+    /// Interrupt playback and forget queued messages
+    pub fn stop(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Failures since the last call, oldest first
+    pub fn take_failures(&self) -> Vec<Failure> {
+        self.failures
+            .lock()
+            .map(|mut f| std::mem::take(&mut *f))
+            .unwrap_or_default()
+    }
+}
+
+/// Synthesize and play queued messages until the speaker is dropped
+async fn speak_queue(
+    mut rx: mpsc::UnboundedReceiver<(u64, String)>,
+    providers: Arc<Vec<Box<dyn SpeechProvider>>>,
+    generation: Arc<AtomicU64>,
+    failures: Arc<Mutex<Vec<Failure>>>,
+) {
+    let fail = |label: &'static str, reason: String| {
+        if let Ok(mut f) = failures.lock() {
+            f.push((label, reason));
+        }
+    };
+
+    while let Some((id, text)) = rx.recv().await {
+        let current = || generation.load(Ordering::Relaxed) == id;
+        if !current() {
+            continue;
+        }
+
+        // 1. Try each provider until one produces audio
+        let mut errors = Vec::new();
+        let mut wav = None;
+        for provider in providers.iter() {
+            match provider.synthesize(&text).await {
+                Ok(w) => {
+                    wav = Some(w);
+                    break;
+                }
+                Err(e) => errors.push(format!("{}: {}", provider.name(), e)),
+            }
+        }
+        let Some(wav) = wav else {
+            let reason = if errors.is_empty() {
+                "no speech provider (set GROQ_API_KEY or install espeak-ng)".to_string()
+            } else {
+                errors.join("; ")
+            };
+            fail("Speech failed", reason);
+            continue;
+        };
+        if !current() {
+            continue;
+        }
+
+        // 2. Play it on a blocking thread, checking for interruptions
+        let generation = generation.clone();
+        let played = tokio::task::spawn_blocking(move || {
+            let (samples, rate) = groq::decode_wav(&wav)?;
+            groq::play(&samples, rate, || generation.load(Ordering::Relaxed) != id)
+        })
+        .await;
+        if let Ok(Err(e)) = played {
+            fail("Playback failed", e.to_string());
+        }
+    }
+}
+
+/// Drop markdown decoration that speech engines would read out: emphasis,
+/// code ticks and heading markers. A `#` inside a line ("C#") stays.
+fn speakable(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let heading = line.trim_start_matches('#');
+            let line =
+                if heading.len() < line.len() && (heading.is_empty() || heading.starts_with(' ')) {
+                    heading
+                } else {
+                    line
+                };
+            line.chars()
+                .filter(|c| !matches!(c, '`' | '*'))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groq_key_stays_with_groq() {
+        let key = |s: &str| Some(s.to_string());
+        let other = "https://api.openai.com/v1/audio/speech";
+        assert_eq!(api_key_for(DEFAULT_TTS_URL, None, key("gsk")), key("gsk"));
+        assert_eq!(api_key_for(other, None, key("gsk")), None);
+        assert_eq!(api_key_for(other, key("sk"), key("gsk")), key("sk"));
+    }
+
+    #[test]
+    fn speakable_strips_markdown_but_not_words() {
+        assert_eq!(
+            speakable("## Setup\nRun `cargo build` **now**"),
+            "Setup\nRun cargo build now"
+        );
+        assert_eq!(speakable("Written in C# and F#"), "Written in C# and F#");
+        assert_eq!(speakable("#hashtag stays"), "#hashtag stays");
+        assert_eq!(speakable("  ###  "), "");
+    }
+}
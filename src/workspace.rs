@@ -0,0 +1,297 @@
+use colored::*;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Stop scanning big trees (home directories, `/`) instead of stalling every command
+const MAX_ENTRIES: usize = 20_000;
+const MAX_DEPTH: usize = 8;
+/// How many paths of each kind are shown to the user and the model
+const MAX_LISTED: usize = 10;
+
+/// Directories that change all the time or are too big to be worth watching
+const IGNORED_DIRS: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    "node_modules",
+    "target",
+    "__pycache__",
+    ".venv",
+    ".cache",
+    ".idea",
+];
+
+/// Files below a directory with their modification time and size
+pub struct Snapshot {
+    root: PathBuf,
+    files: HashMap<PathBuf, (Option<SystemTime>, u64)>,
+    /// Hit `MAX_ENTRIES`; a diff against this would report phantom deletions
+    incomplete: bool,
+}
+
+/// Files a command created, modified or deleted, relative to the directory it ran in
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub created: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+}
+
+impl Snapshot {
+    /// Scan `root`, skipping `IGNORED_DIRS` and the top-level `.gitignore` patterns.
+    /// None for `/` and the home directory, and for any tree past `MAX_ENTRIES`:
+    /// the diff would be empty anyway, so callers can skip the second scan.
+    pub fn take(root: &Path) -> Option<Snapshot> {
+        if root.parent().is_none() || dirs_next::home_dir().is_some_and(|home| home == root) {
+            return None;
+        }
+
+        let mut snapshot = Snapshot {
+            root: root.to_path_buf(),
+            files: HashMap::new(),
+            incomplete: false,
+        };
+        let ignore = read_gitignore(root);
+        snapshot.scan(root, 0, &ignore);
+        (!snapshot.incomplete).then_some(snapshot)
+    }
+
+    fn scan(&mut self, dir: &Path, depth: usize, ignore: &[String]) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            if self.files.len() >= MAX_ENTRIES {
+                self.incomplete = true;
+                return;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if ignore.iter().any(|pattern| glob_match(pattern, &name)) {
+                continue;
+            }
+
+            let path = entry.path();
+            if file_type.is_dir() {
+                if depth + 1 < MAX_DEPTH && !IGNORED_DIRS.contains(&name.as_str()) {
+                    self.scan(&path, depth + 1, ignore);
+                }
+                continue;
+            }
+
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            let relative = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+            self.files
+                .insert(relative, (meta.modified().ok(), meta.len()));
+        }
+    }
+
+    /// What changed between this snapshot and `after` (taken of the same directory)
+    pub fn diff(&self, after: &Snapshot) -> Changes {
+        let mut changes = Changes::default();
+        if self.incomplete || after.incomplete || self.root != after.root {
+            return changes;
+        }
+
+        for (path, stamp) in &after.files {
+            match self.files.get(path) {
+                None => changes.created.push(path.clone()),
+                Some(before) if before != stamp => changes.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        for path in self.files.keys() {
+            if !after.files.contains_key(path) {
+                changes.deleted.push(path.clone());
+            }
+        }
+
+        changes.created.sort();
+        changes.modified.sort();
+        changes.deleted.sort();
+        changes
+    }
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+
+    /// The first new or rewritten file with one of `extensions`
+    pub fn find_written(&self, extensions: &[&str]) -> Option<&PathBuf> {
+        self.created.iter().chain(&self.modified).find(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+        })
+    }
+
+    /// `+ new.txt`, `~ changed.rs`, `- removed.log` lines for the terminal
+    pub fn user_view(&self) -> String {
+        let mut out = String::new();
+        for (paths, mark) in [
+            (&self.created, "+".green()),
+            (&self.modified, "~".yellow()),
+            (&self.deleted, "-".red()),
+        ] {
+            for path in paths.iter().take(MAX_LISTED) {
+                out.push_str(&format!("  {} {}\n", mark, path.display()));
+            }
+            if paths.len() > MAX_LISTED {
+                out.push_str(&format!(
+                    "  {} {}\n",
+                    mark,
+                    format!("... and {} more", paths.len() - MAX_LISTED).dimmed()
+                ));
+            }
+        }
+        format!("{}\n{}", "Files changed:".bold(), out.trim_end())
+    }
+
+    /// `files_created: a, b` style lines appended to COMMAND_OUTPUT
+    pub fn ai_view(&self) -> String {
+        let list = |paths: &[PathBuf]| {
+            let mut shown: Vec<String> = paths
+                .iter()
+                .take(MAX_LISTED)
+                .map(|p| p.display().to_string())
+                .collect();
+            if paths.len() > MAX_LISTED {
+                shown.push(format!("... and {} more", paths.len() - MAX_LISTED));
+            }
+            shown.join(", ")
+        };
+
+        let mut out = String::new();
+        for (label, paths) in [
+            ("files_created", &self.created),
+            ("files_modified", &self.modified),
+            ("files_deleted", &self.deleted),
+        ] {
+            if !paths.is_empty() {
+                out.push_str(&format!("{}: {}\n", label, list(paths)));
+            }
+        }
+        out.trim_end().to_string()
+    }
+}
+
+/// Plain names and `*` wildcards from `root/.gitignore`; negations and
+/// nested paths are beyond what we need for a change report
+fn read_gitignore(root: &Path) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(root.join(".gitignore")) else {
+        return Vec::new();
+    };
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('!'))
+        .map(|l| l.trim_start_matches('/').trim_end_matches('/').to_string())
+        .filter(|l| !l.is_empty() && !l.contains('/'))
+        .collect()
+}
+
+/// Match a name against a pattern where `*` stands for any run of characters
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == name;
+    };
+    let Some(mut remaining) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut pieces: Vec<&str> = rest.split('*').collect();
+    let last = pieces.pop().unwrap_or("");
+    for piece in pieces {
+        match remaining.find(piece) {
+            Some(pos) => remaining = &remaining[pos + piece.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= last.len() && remaining.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ai-terminal-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.log", "build.log"));
+        assert!(!glob_match("*.log", "build.log.gz"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(glob_match("a*b*c", "a-x-b-y-c"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("dist", "dist"));
+        assert!(!glob_match("dist", "dist2"));
+    }
+
+    #[test]
+    fn gitignore_keeps_plain_names() {
+        let dir = scratch("gitignore");
+        fs::write(
+            dir.join(".gitignore"),
+            "# comment\n\n/dist/\n*.log\n!keep.log\nsrc/generated\n  build  \n",
+        )
+        .unwrap();
+        assert_eq!(read_gitignore(&dir), ["dist", "*.log", "build"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diff_reports_each_kind_of_change() {
+        let dir = scratch("diff");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("old.txt"), "old").unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        let before = Snapshot::take(&dir).unwrap();
+
+        fs::write(dir.join("src/main.rs"), "fn main() { println!(); }").unwrap();
+        fs::remove_file(dir.join("old.txt")).unwrap();
+        fs::write(dir.join("new.txt"), "new").unwrap();
+        fs::write(dir.join("debug.log"), "ignored").unwrap();
+        let changes = before.diff(&Snapshot::take(&dir).unwrap());
+
+        assert_eq!(changes.created, [PathBuf::from("new.txt")]);
+        assert_eq!(changes.modified, [PathBuf::from("src/main.rs")]);
+        assert_eq!(changes.deleted, [PathBuf::from("old.txt")]);
+        assert_eq!(
+            changes.find_written(&["rs"]),
+            Some(&PathBuf::from("src/main.rs"))
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn no_snapshot_past_the_entry_cap() {
+        let dir = scratch("cap");
+        for i in 0..=MAX_ENTRIES {
+            fs::write(dir.join(i.to_string()), "").unwrap();
+        }
+        // A partial scan would make every unseen file look deleted
+        assert!(Snapshot::take(&dir).is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}